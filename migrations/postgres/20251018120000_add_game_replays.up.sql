@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS game_replays
+(
+    id          SERIAL      PRIMARY KEY,
+    game_id     INTEGER     NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    events      TEXT        NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE game_replays ADD COLUMN IF NOT EXISTS seed BIGINT NOT NULL DEFAULT 0;
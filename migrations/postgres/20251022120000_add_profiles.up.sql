@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS profiles
+(
+    user_id       INTEGER     PRIMARY KEY,
+    xp_points     INTEGER     NOT NULL DEFAULT 0,
+    xp_level      SMALLINT    NOT NULL DEFAULT 1,
+    game_count    INTEGER     NOT NULL DEFAULT 0,
+    country_id    TEXT        NOT NULL DEFAULT 'hu',
+    castle_level  SMALLINT    NOT NULL DEFAULT 1
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS games
+(
+    id          SERIAL      PRIMARY KEY,
+    started_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS replay_viewers
+(
+    game_id     INTEGER     NOT NULL,
+    user_id     INTEGER     NOT NULL,
+    PRIMARY KEY (game_id, user_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS golds INTEGER NOT NULL DEFAULT 3000;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS game_results
+(
+    game_id     INTEGER     NOT NULL,
+    seat        SMALLINT    NOT NULL,
+    user_id     INTEGER,
+    placing     SMALLINT    NOT NULL,
+    points      INTEGER     NOT NULL,
+    forfeited   SMALLINT    NOT NULL,
+    xp          INTEGER     NOT NULL,
+    PRIMARY KEY (game_id, seat)
+);
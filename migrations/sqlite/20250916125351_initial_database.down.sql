@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS choice_questions;
+DROP TABLE IF EXISTS tip_questions;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS game_replays
+(
+    id          INTEGER     PRIMARY KEY AUTOINCREMENT,
+    game_id     INTEGER     NOT NULL,
+    created_at  TEXT        NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    events      TEXT        NOT NULL
+);
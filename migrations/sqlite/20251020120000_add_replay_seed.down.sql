@@ -0,0 +1 @@
+ALTER TABLE game_replays DROP COLUMN seed;
@@ -0,0 +1 @@
+ALTER TABLE game_replays ADD COLUMN seed INTEGER NOT NULL DEFAULT 0;
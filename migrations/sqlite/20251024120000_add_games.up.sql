@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS games
+(
+    id          INTEGER     PRIMARY KEY AUTOINCREMENT,
+    started_at  TEXT        NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
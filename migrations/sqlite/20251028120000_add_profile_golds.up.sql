@@ -0,0 +1 @@
+ALTER TABLE profiles ADD COLUMN golds INTEGER NOT NULL DEFAULT 3000;
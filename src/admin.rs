@@ -0,0 +1,534 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::{Extension, Json, Router};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::app::{ActiveGames, FriendlyRooms, GameRegistry, SharedPlayerState, SharedState};
+use crate::channels::ChannelErrorResponse;
+use crate::router::stop_spectating;
+use crate::triviador::game_player_data::PlayerName;
+use crate::village::start::friendly_game::{ActiveSepRoom, OpponentType};
+
+/// Everything the admin API can look at and change
+#[derive(Clone)]
+pub(crate) struct AdminState {
+	pub sessions: SharedState,
+	pub rooms: FriendlyRooms,
+	/// Games of the users, for forfeiting the game of a kicked player
+	pub active_games: ActiveGames,
+	pub games: GameRegistry,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct SessionInfo {
+	cid: i32,
+	name: String,
+	waithall: String,
+	listen_ready: bool,
+	room: Option<u16>,
+	spectating: Option<u16>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct RoomSeat {
+	opponent: String,
+	name: Option<String>,
+	ready: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct RoomInfo {
+	code: u16,
+	map: Option<String>,
+	seats: Vec<RoomSeat>,
+	spectators: u16,
+	/// Id of the game the room is playing
+	game_id: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct GamePlayer {
+	seat: PlayerName,
+	name: String,
+	/// Not set for robots
+	user_id: Option<i32>,
+	points: i16,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct GameInfo {
+	game_id: u32,
+	state: u8,
+	round: u8,
+	phase: u8,
+	active_player: Option<PlayerName>,
+	players: Vec<GamePlayer>,
+	running_secs: u64,
+}
+
+impl AdminState {
+	pub(crate) async fn sessions(&self) -> Vec<SessionInfo> {
+		let mut sessions = Vec::with_capacity(self.sessions.len());
+		for session in self.session_states().await {
+			let state = session.read_lock().await;
+			sessions.push(SessionInfo {
+				cid: state.player_id,
+				name: state.player_name.clone(),
+				waithall: format!("{:?}", state.current_waithall),
+				listen_ready: state.is_listen_ready,
+				room: state.friendly_game_code,
+				spectating: state.spectating,
+			});
+		}
+		sessions.sort_by_key(|session| session.cid);
+		sessions
+	}
+
+	/// Drops the session and closes its listen channel, the client has to log in again. The player
+	/// leaves their room and forfeits the game they are playing
+	pub(crate) async fn kick(&self, cid: i32) -> bool {
+		let Some((_, session)) = self.sessions.remove_async(&cid).await else {
+			return false;
+		};
+		stop_spectating(&session, &self.rooms).await;
+		let (player_id, code) = {
+			let state = session.read_lock().await;
+			state.listen_channel.close();
+			(state.player_id, state.friendly_game_code)
+		};
+		if let Some(code) = code {
+			self.leave_room(code, player_id).await;
+		}
+		if let Some(game) = self.active_games.remove_async(&player_id).await
+			&& let Err(e) = game.forfeit_player(player_id).await
+		{
+			warn!(
+				"Unable to forfeit the game of kicked player {}: {}",
+				player_id, e
+			);
+		}
+		info!("Session {} was kicked", cid);
+		true
+	}
+
+	/// Frees the seat of the player, the room is closed if it's their own
+	async fn leave_room(&self, code: u16, player_id: i32) {
+		let Some(mut room) = self.rooms.get_async(&code).await else {
+			return;
+		};
+		let player = OpponentType::Player(player_id);
+		if room.get().player1 == player {
+			drop(room);
+			self.close_room(code).await;
+			return;
+		}
+		room.get_mut().remove_opponent(player).await;
+		room.get().send_state_to_players().await;
+	}
+
+	pub(crate) async fn rooms(&self) -> Vec<RoomInfo> {
+		let mut rooms: Vec<(u16, ActiveSepRoom)> = Vec::new();
+		self.rooms
+			.0
+			.scan_async(|code, room| rooms.push((*code, room.clone())))
+			.await;
+		let mut infos = Vec::with_capacity(rooms.len());
+		for (code, room) in rooms {
+			let game_id = match &room.game {
+				Some(game) => game.read().await.players.as_ref().map(|info| info.game_id),
+				None => None,
+			};
+			let mut seats = vec![RoomSeat {
+				opponent: format!("{:?}", room.player1),
+				name: Some(room.player1_name.clone()),
+				ready: room.player1_ready,
+			}];
+			for (opponent, name, ready) in [
+				(&room.player2, &room.player2_name, room.player2_ready),
+				(&room.player3, &room.player3_name, room.player3_ready),
+			] {
+				if let Some(opponent) = opponent {
+					seats.push(RoomSeat {
+						opponent: format!("{:?}", opponent),
+						name: name.clone(),
+						ready,
+					});
+				}
+			}
+			infos.push(RoomInfo {
+				code,
+				map: room.map.clone(),
+				seats,
+				spectators: room.spectators,
+				game_id,
+			});
+		}
+		infos.sort_by_key(|room| room.code);
+		infos
+	}
+
+	/// Removes the room and sends its players and spectators out of it with an error message, a
+	/// game started from the room keeps running for its players
+	pub(crate) async fn close_room(&self, code: u16) -> bool {
+		let Some(room) = self.rooms.remove_async(&code).await else {
+			return false;
+		};
+		let error = quick_xml::se::to_string(&ChannelErrorResponse::new()).unwrap();
+		for session in self.session_states().await {
+			let mut state = session.write_lock().await;
+			let mut send_error = false;
+			if state.friendly_game_code == Some(code) {
+				state.friendly_game_code = None;
+				// the players of a running game get their messages from the game
+				send_error = room.game.is_none();
+			}
+			if state.spectating == Some(code) {
+				state.spectating = None;
+				if let Some(game) = &room.game {
+					game.remove_spectator(state.player_id).await;
+				}
+				send_error = true;
+			}
+			if send_error {
+				let _ = state.listen_channel.try_send_message(error.clone());
+			}
+		}
+		info!("Room {} was closed", code);
+		true
+	}
+
+	pub(crate) async fn games(&self) -> Vec<GameInfo> {
+		let mut games = Vec::new();
+		for (game_id, running) in self.games.running().await {
+			let game = running.game.read().await;
+			let state = &game.state;
+			let players = match &game.players {
+				Some(info) => [
+					(PlayerName::Player1, &info.p1_name, &info.pd1),
+					(PlayerName::Player2, &info.p2_name, &info.pd2),
+					(PlayerName::Player3, &info.p3_name, &info.pd3),
+				]
+				.into_iter()
+				.map(|(seat, name, data)| GamePlayer {
+					seat,
+					name: name.clone(),
+					user_id: (!data.is_bot()).then_some(data.id),
+					points: state.players_points.get_player_points(&seat),
+				})
+				.collect(),
+				None => vec![],
+			};
+			games.push(GameInfo {
+				game_id,
+				state: state.game_state.state,
+				round: state.game_state.round,
+				phase: state.game_state.phase,
+				active_player: state.active_player,
+				players,
+				running_secs: running.started_at.elapsed().as_secs(),
+			});
+		}
+		games
+	}
+
+	/// Stops the game, its players and spectators get an error message and its room can start a
+	/// new game
+	pub(crate) async fn abort_game(&self, game_id: u32) -> bool {
+		let Some(running) = self.games.get(game_id).await else {
+			return false;
+		};
+		self.games.abort(game_id).await;
+		running.game.send_error_to_all().await;
+		if let Some(code) = running.room {
+			self.rooms.clear_game(code, &running.game).await;
+		}
+		info!("Game {} is aborted by an admin", game_id);
+		true
+	}
+
+	async fn session_states(&self) -> Vec<SharedPlayerState> {
+		let mut sessions = Vec::with_capacity(self.sessions.len());
+		self.sessions
+			.scan_async(|_, session| sessions.push(session.clone()))
+			.await;
+		sessions
+	}
+}
+
+/// The admin API, every request needs the token in an `Authorization: Bearer` header
+pub(crate) fn router(token: String, state: AdminState) -> Router {
+	Router::new()
+		.route("/admin/sessions", get(list_sessions))
+		.route("/admin/sessions/:cid", delete(kick_session))
+		.route("/admin/rooms", get(list_rooms))
+		.route("/admin/rooms/:code", delete(close_room))
+		.route("/admin/games", get(list_games))
+		.route("/admin/games/:game_id", delete(abort_game))
+		.route_layer(middleware::from_fn_with_state(
+			Arc::<str>::from(token),
+			require_token,
+		))
+		.layer(Extension(state))
+}
+
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+	let given = request
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+	match given {
+		Some(given) if tokens_match(given, &token) => next.run(request).await,
+		_ => {
+			warn!("Unauthorized admin request to {}", request.uri());
+			StatusCode::UNAUTHORIZED.into_response()
+		}
+	}
+}
+
+/// Compares the whole tokens, so the time taken doesn't tell how much of a guess was right
+fn tokens_match(given: &str, token: &str) -> bool {
+	given.len() == token.len()
+		&& given
+			.bytes()
+			.zip(token.bytes())
+			.fold(0, |difference, (a, b)| difference | (a ^ b))
+			== 0
+}
+
+fn found(found: bool) -> StatusCode {
+	if found {
+		StatusCode::NO_CONTENT
+	} else {
+		StatusCode::NOT_FOUND
+	}
+}
+
+async fn list_sessions(Extension(admin): Extension<AdminState>) -> Json<Vec<SessionInfo>> {
+	Json(admin.sessions().await)
+}
+
+async fn kick_session(Extension(admin): Extension<AdminState>, Path(cid): Path<i32>) -> StatusCode {
+	found(admin.kick(cid).await)
+}
+
+async fn list_rooms(Extension(admin): Extension<AdminState>) -> Json<Vec<RoomInfo>> {
+	Json(admin.rooms().await)
+}
+
+async fn close_room(Extension(admin): Extension<AdminState>, Path(code): Path<u16>) -> StatusCode {
+	found(admin.close_room(code).await)
+}
+
+async fn list_games(Extension(admin): Extension<AdminState>) -> Json<Vec<GameInfo>> {
+	Json(admin.games().await)
+}
+
+async fn abort_game(
+	Extension(admin): Extension<AdminState>,
+	Path(game_id): Path<u32>,
+) -> StatusCode {
+	found(admin.abort_game(game_id).await)
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::body::Body;
+	use scc::HashMap;
+	use tower::ServiceExt;
+
+	use super::*;
+	use crate::app::tests::test_game;
+	use crate::app::{ActiveGames, ListenPlayerChannel, RunningGame};
+	use crate::village::start::friendly_game::OpponentType;
+
+	async fn admin_state() -> AdminState {
+		let sessions: SharedState = Arc::new(HashMap::new());
+		for (cid, name, code) in [(1, "felso", Some(1234)), (6, "Lajos", None)] {
+			let session = SharedPlayerState::new();
+			{
+				let mut state = session.write_lock().await;
+				state.player_id = cid;
+				state.player_name = name.to_string();
+				state.friendly_game_code = code;
+			}
+			sessions.upsert_async(cid, session).await;
+		}
+		let rooms = FriendlyRooms::new();
+		let mut room = ActiveSepRoom::new(OpponentType::Player(1), "felso");
+		room.add_opponent(OpponentType::Code, None).unwrap();
+		rooms.insert_async(1234, room).await.unwrap();
+		AdminState {
+			sessions,
+			rooms,
+			active_games: ActiveGames::new(),
+			games: GameRegistry::new(),
+		}
+	}
+
+	#[tokio::test]
+	async fn lists_and_kicks_sessions() {
+		let admin = admin_state().await;
+		let sessions = admin.sessions().await;
+		assert_eq!(sessions.len(), 2);
+		assert_eq!(sessions[0].name, "felso");
+		assert_eq!(sessions[0].room, Some(1234));
+
+		let listen_channel = admin
+			.sessions
+			.get_async(&6)
+			.await
+			.unwrap()
+			.get()
+			.read_lock()
+			.await
+			.listen_channel
+			.clone();
+		assert!(admin.kick(6).await);
+		assert!(!admin.kick(6).await);
+		assert_eq!(admin.sessions().await.len(), 1);
+		// a waiting listen request of the kicked client returns
+		assert!(listen_channel.is_closed());
+		assert!(listen_channel.recv_message().await.is_err());
+	}
+
+	#[tokio::test]
+	async fn kicked_players_leave_their_room_and_game() {
+		let admin = admin_state().await;
+		let session = SharedPlayerState::new();
+		{
+			let mut state = session.write_lock().await;
+			state.player_id = 5;
+			state.friendly_game_code = Some(1234);
+		}
+		admin.sessions.upsert_async(5, session).await;
+		admin
+			.rooms
+			.get_async(&1234)
+			.await
+			.unwrap()
+			.get_mut()
+			.add_opponent(OpponentType::Player(5), Some("Player1".to_string()))
+			.unwrap();
+		let game = test_game(None);
+		admin.active_games.insert_async(5, game.arc_clone()).await;
+
+		assert!(admin.kick(5).await);
+		let rooms = admin.rooms().await;
+		assert_eq!(rooms[0].seats.len(), 1);
+		assert!(admin.active_games.get_async(&5).await.is_none());
+		assert!(
+			game.read()
+				.await
+				.state
+				.forfeited_players
+				.contains(&PlayerName::Player1)
+		);
+
+		// the room of its owner is closed
+		assert!(admin.kick(1).await);
+		assert!(admin.rooms().await.is_empty());
+	}
+
+	#[tokio::test]
+	async fn closing_a_room_frees_its_players() {
+		let admin = admin_state().await;
+		let rooms = admin.rooms().await;
+		assert_eq!(rooms.len(), 1);
+		assert_eq!(rooms[0].seats.len(), 2);
+		assert_eq!(rooms[0].game_id, None);
+
+		let listen_channel = admin
+			.sessions
+			.get_async(&1)
+			.await
+			.unwrap()
+			.get()
+			.read_lock()
+			.await
+			.listen_channel
+			.clone();
+		assert!(admin.close_room(1234).await);
+		assert_eq!(listen_channel.recv_message().await.unwrap(), "<ERROR/>");
+		assert!(admin.rooms().await.is_empty());
+		assert!(
+			admin
+				.sessions()
+				.await
+				.iter()
+				.all(|session| session.room.is_none())
+		);
+		assert!(!admin.close_room(1234).await);
+		assert!(!admin.abort_game(1).await);
+	}
+
+	#[tokio::test]
+	async fn aborted_games_notify_spectators_and_free_the_room() {
+		let admin = admin_state().await;
+		let game = test_game(None);
+		admin.rooms.get_async(&1234).await.unwrap().get_mut().game = Some(game.arc_clone());
+		let spectator = ListenPlayerChannel::new();
+		game.add_spectator(6, spectator.clone()).await;
+		// the game state sent to new spectators
+		spectator.recv_message().await.unwrap();
+		admin
+			.games
+			.start(
+				1,
+				RunningGame::new(game.arc_clone(), vec![5]).with_room(1234),
+				ActiveGames::new(),
+				admin.rooms.clone(),
+				std::future::pending(),
+			)
+			.await;
+
+		assert!(admin.abort_game(1).await);
+		assert_eq!(spectator.recv_message().await.unwrap(), "<ERROR/>");
+		let room = admin.rooms.get_async(&1234).await.unwrap();
+		assert!(room.get().game.is_none());
+	}
+
+	#[tokio::test]
+	async fn requests_need_the_token() {
+		let router = router("0123456789abcdef".to_string(), admin_state().await);
+		for authorization in [
+			None,
+			Some("Bearer 0123456789abcdeg"),
+			Some("0123456789abcdef"),
+		] {
+			let mut request = Request::get("/admin/sessions");
+			if let Some(authorization) = authorization {
+				request = request.header(header::AUTHORIZATION, authorization);
+			}
+			let response = router
+				.clone()
+				.oneshot(request.body(Body::empty()).unwrap())
+				.await
+				.unwrap();
+			assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+		}
+		let response = router
+			.oneshot(
+				Request::get("/admin/sessions")
+					.header(header::AUTHORIZATION, "Bearer 0123456789abcdef")
+					.body(Body::empty())
+					.unwrap(),
+			)
+			.await
+			.unwrap();
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	#[test]
+	fn token_must_match_exactly() {
+		assert!(tokens_match("0123456789abcdef", "0123456789abcdef"));
+		assert!(!tokens_match("0123456789abcdeg", "0123456789abcdef"));
+		assert!(!tokens_match("0123456789abcde", "0123456789abcdef"));
+	}
+}
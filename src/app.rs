@@ -1,6 +1,8 @@
 use std::hash::RandomState;
 use std::sync::Arc;
+use std::time::Instant;
 
+use anyhow::Context;
 use axum::body::Body;
 use axum::extract::Request;
 use axum::http::StatusCode;
@@ -13,17 +15,27 @@ use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use scc::HashMap;
 use scc::hash_map::OccupiedEntry;
-use sqlx::postgres::PgPool;
-use tokio::sync::RwLock;
-use tracing::{error, trace};
+use tokio::sync::{RwLock, watch};
+use tokio::task::AbortHandle;
+use tracing::{error, info, trace, warn};
 
+use crate::admin::{self, AdminState};
+use crate::bot::strategy::BotDifficulty;
 use crate::channels::command::request::CommandRoot;
 use crate::channels::{BodyChannelType, parse_xml_multiple};
+use crate::config::config;
 use crate::router::{client_castle, countries, friends, game, help, mobil};
+use crate::storage::{self, SharedStorage};
+use crate::triviador::game::SharedTrivGame;
+use crate::triviador::game_state::GameState;
+use crate::triviador::map::MapRegistry;
+use crate::triviador::question_provider;
 use crate::users::ServerCommand;
 use crate::village::start::friendly_game::{ActiveSepRoom, OpponentType};
 use crate::village::waithall::Waithall;
 
+/// The rooms by their code, the codes are random even if `GAME_SEED` is set so restarts don't
+/// hand out the same codes
 #[derive(Clone)]
 pub struct FriendlyRooms(pub std::sync::Arc<HashMap<u16, ActiveSepRoom>>);
 
@@ -48,11 +60,25 @@ impl FriendlyRooms {
 		self.0.get_async(key).await
 	}
 
-	#[allow(dead_code)]
 	pub async fn remove_async(&self, key: &u16) -> Option<ActiveSepRoom> {
 		self.0.remove_async(key).await.map(|(_, v)| v)
 	}
 
+	/// Forgets the game of the room if it's still the given one, the room may have started a newer
+	/// game
+	pub(crate) async fn clear_game(&self, code: u16, game: &SharedTrivGame) {
+		if let Some(mut room) = self.get_async(&code).await {
+			let room = room.get_mut();
+			if room
+				.game
+				.as_ref()
+				.is_some_and(|current| current.ptr_eq(game))
+			{
+				room.game = None;
+			}
+		}
+	}
+
 	pub fn get_next_available(&self) -> usize {
 		let mut rng = StdRng::from_entropy();
 		let num = rng.gen_range(1000..=9999);
@@ -61,7 +87,168 @@ impl FriendlyRooms {
 	}
 }
 
-type SharedState = Arc<HashMap<i32, SharedPlayerState>>;
+/// Games in progress by the user ids of their human players
+#[derive(Clone)]
+pub struct ActiveGames(pub Arc<HashMap<i32, SharedTrivGame>>);
+
+impl ActiveGames {
+	pub fn new() -> Self {
+		ActiveGames(Arc::new(HashMap::new()))
+	}
+
+	pub async fn insert_async(&self, user_id: i32, game: SharedTrivGame) {
+		self.0.upsert_async(user_id, game).await;
+	}
+
+	pub async fn get_async(&self, user_id: &i32) -> Option<SharedTrivGame> {
+		self.0
+			.get_async(user_id)
+			.await
+			.map(|entry| entry.get().arc_clone())
+	}
+
+	pub async fn remove_async(&self, user_id: &i32) -> Option<SharedTrivGame> {
+		self.0.remove_async(user_id).await.map(|(_, v)| v)
+	}
+
+	/// Removes the game of the user only if it's the given game, the user may be in a newer one
+	pub async fn remove_game(&self, user_id: &i32, game: &SharedTrivGame) {
+		self.0
+			.remove_if_async(user_id, |active| active.ptr_eq(game))
+			.await;
+	}
+}
+
+/// A game which is being played
+#[derive(Clone, Debug)]
+pub(crate) struct RunningGame {
+	pub game: SharedTrivGame,
+	/// User ids of the human players
+	pub user_ids: Vec<i32>,
+	/// Code of the friendly room the game was started from
+	pub room: Option<u16>,
+	pub started_at: Instant,
+	/// Stops the task of the game, set when it's started
+	abort: Option<AbortHandle>,
+}
+
+impl RunningGame {
+	pub(crate) fn new(game: SharedTrivGame, user_ids: Vec<i32>) -> RunningGame {
+		RunningGame {
+			game,
+			user_ids,
+			room: None,
+			started_at: Instant::now(),
+			abort: None,
+		}
+	}
+
+	pub(crate) fn with_room(mut self, code: u16) -> RunningGame {
+		self.room = Some(code);
+		self
+	}
+
+	pub(crate) async fn game_state(&self) -> GameState {
+		self.game.read().await.state.game_state.clone()
+	}
+}
+
+/// Every running game by its id. The games are started through the registry, so finished and
+/// crashed games are both removed
+#[derive(Clone, Default)]
+pub(crate) struct GameRegistry(Arc<HashMap<u32, RunningGame>>);
+
+impl GameRegistry {
+	pub(crate) fn new() -> Self {
+		GameRegistry::default()
+	}
+
+	pub(crate) async fn get(&self, game_id: u32) -> Option<RunningGame> {
+		self.0
+			.get_async(&game_id)
+			.await
+			.map(|entry| entry.get().clone())
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	/// Every running game ordered by id
+	pub(crate) async fn running(&self) -> Vec<(u32, RunningGame)> {
+		let mut games = Vec::with_capacity(self.len());
+		self.0
+			.scan_async(|game_id, running| games.push((*game_id, running.clone())))
+			.await;
+		games.sort_by_key(|(game_id, _)| *game_id);
+		games
+	}
+
+	/// Stops the game, it is unregistered like a crashed game. Returns false if there is no such
+	/// game
+	pub(crate) async fn abort(&self, game_id: u32) -> bool {
+		let Some(running) = self.get(game_id).await else {
+			return false;
+		};
+		if let Some(abort) = running.abort {
+			abort.abort();
+		}
+		true
+	}
+
+	/// Registers the game for rejoining, runs the game task and unregisters the game when it
+	/// returns or panics, its players can join other games afterwards
+	pub(crate) async fn start(
+		&self,
+		game_id: u32,
+		running: RunningGame,
+		active_games: ActiveGames,
+		friendly_rooms: FriendlyRooms,
+		task: impl Future<Output = ()> + Send + 'static,
+	) {
+		for user_id in &running.user_ids {
+			active_games
+				.insert_async(*user_id, running.game.arc_clone())
+				.await;
+		}
+		let handle = tokio::spawn(task);
+		let mut running = running;
+		running.abort = Some(handle.abort_handle());
+		// registered before the supervisor starts, so it can't miss the entry
+		self.0.upsert_async(game_id, running).await;
+		info!("Game {} started, {} games are running", game_id, self.len());
+		let registry = self.clone();
+		tokio::spawn(async move {
+			let result = handle.await;
+			let Some((_, running)) = registry.0.remove_async(&game_id).await else {
+				return;
+			};
+			let game_state = running.game_state().await;
+			match result {
+				Ok(()) => info!(
+					"Game {} finished after {}s",
+					game_id,
+					running.started_at.elapsed().as_secs()
+				),
+				Err(e) if e.is_cancelled() => info!("Game {} was aborted", game_id),
+				Err(e) => error!("Game {} crashed in {:?}: {}", game_id, game_state, e),
+			}
+			// crashed and aborted games keep what was recorded until then
+			if let Err(e) = running.game.save_replay().await {
+				warn!("Failed to save the replay of game {}: {}", game_id, e);
+			}
+			for user_id in &running.user_ids {
+				active_games.remove_game(user_id, &running.game).await;
+			}
+			// the room must not keep a game which isn't played anymore
+			if let Some(code) = running.room {
+				friendly_rooms.clear_game(code, &running.game).await;
+			}
+		});
+	}
+}
+
+pub(crate) type SharedState = Arc<HashMap<i32, SharedPlayerState>>;
 
 #[derive(Debug)]
 pub(crate) struct PlayerState {
@@ -71,6 +258,8 @@ pub(crate) struct PlayerState {
 	pub(crate) player_id: i32,
 	pub(crate) player_name: String,
 	pub(crate) friendly_game_code: Option<u16>,
+	/// Code of the friendly room whose game the player watches
+	pub(crate) spectating: Option<u16>,
 	pub(crate) command_channel: ServerCommandChannel,
 	pub(crate) listen_channel: ListenPlayerChannel,
 }
@@ -79,7 +268,7 @@ pub(crate) struct PlayerState {
 pub struct SharedPlayerState(pub Arc<RwLock<PlayerState>>);
 
 impl SharedPlayerState {
-	fn new() -> Self {
+	pub(crate) fn new() -> Self {
 		let val = PlayerState {
 			is_logged_in: false,
 			is_listen_ready: false,
@@ -87,6 +276,7 @@ impl SharedPlayerState {
 			player_id: 0,
 			player_name: "Anonymous".to_string(),
 			friendly_game_code: None,
+			spectating: None,
 			command_channel: ServerCommandChannel::new(),
 			listen_channel: ListenPlayerChannel::new(),
 		};
@@ -106,6 +296,8 @@ impl SharedPlayerState {
 pub struct PlayerChannel<T> {
 	tx: flume::Sender<T>,
 	rx: flume::Receiver<T>,
+	/// Set when the channel is closed, receivers waiting for a message are woken up
+	closed: Arc<watch::Sender<bool>>,
 }
 
 impl<T: Clone> Default for PlayerChannel<T> {
@@ -116,16 +308,44 @@ impl<T: Clone> Default for PlayerChannel<T> {
 
 impl<T: Clone> PlayerChannel<T> {
 	pub(crate) fn new() -> Self {
-		let (tx, rx) = flume::bounded(8);
-		PlayerChannel { tx, rx }
+		let (tx, rx) = flume::bounded(config().server.channel_capacity);
+		PlayerChannel {
+			tx,
+			rx,
+			closed: Arc::new(watch::channel(false).0),
+		}
 	}
 
 	pub(crate) async fn send_message(&self, msg: T) -> Result<(), flume::SendError<T>> {
+		if self.is_closed() {
+			return Err(flume::SendError(msg));
+		}
 		self.tx.send_async(msg).await
 	}
 
+	/// Sends a message without waiting for free space in the channel
+	pub(crate) fn try_send_message(&self, msg: T) -> Result<(), flume::TrySendError<T>> {
+		if self.is_closed() {
+			return Err(flume::TrySendError::Disconnected(msg));
+		}
+		self.tx.try_send(msg)
+	}
+
 	pub(crate) async fn recv_message(&self) -> Result<T, flume::RecvError> {
-		self.rx.recv_async().await
+		let mut closed = self.closed.subscribe();
+		tokio::select! {
+			msg = self.rx.recv_async() => msg,
+			_ = closed.wait_for(|closed| *closed) => Err(flume::RecvError::Disconnected),
+		}
+	}
+
+	/// Every clone of the channel stops sending and receiving, waiting receivers return an error
+	pub(crate) fn close(&self) {
+		self.closed.send_replace(true);
+	}
+
+	pub(crate) fn is_closed(&self) -> bool {
+		*self.closed.borrow()
 	}
 
 	pub(crate) fn clear_rx(&self) {
@@ -195,24 +415,33 @@ impl GroupedCommChannels {
 }
 
 pub struct App {
-	db: PgPool,
+	storage: SharedStorage,
 }
 
 impl App {
 	pub async fn new() -> Result<Self, anyhow::Error> {
-		let db = PgPool::connect(&dotenvy::var("DATABASE_URL").expect("DATABASE_URL not defined!"))
-			.await?;
-		sqlx::migrate!().run(&db).await?;
-		Ok(Self { db })
+		let url = config()
+			.server
+			.database_url
+			.as_deref()
+			.context("DATABASE_URL not defined!")?;
+		let storage = storage::connect(url).await?;
+		Ok(Self { storage })
 	}
 
 	pub async fn serve(self) -> Result<(), AppError> {
-		trace!("Starting server on port 8080");
+		let address = config().server.bind_address;
+		trace!("Starting server on {}", address);
 		let friendly_rooms: FriendlyRooms = FriendlyRooms::new();
+		let active_games: ActiveGames = ActiveGames::new();
+		let games = GameRegistry::new();
+		let maps = MapRegistry::load_dir(&config().server.maps_dir)?;
+		let questions = question_provider::from_config(&self.storage)?;
 		let shared_state: SharedState = Arc::new(HashMap::new());
 
 		let mut room = ActiveSepRoom::new(OpponentType::Player(65), "Lajos");
-		room.add_opponent(OpponentType::Robot, None).unwrap();
+		room.add_opponent(OpponentType::Robot(BotDifficulty::default()), None)
+			.unwrap();
 		room.add_opponent(OpponentType::Code, None).unwrap();
 
 		friendly_rooms.insert_async(0000, room).await.unwrap();
@@ -223,7 +452,8 @@ impl App {
 			.route("/client_countries.php", get(countries))
 			.route("/client_friends.php", post(friends))
 			.route("/client_castle.php", get(client_castle))
-			.layer(Extension(self.db.clone()));
+			.layer(Extension(self.storage.clone()))
+			.layer(Extension(maps.clone()));
 		// .route("/client_extdata.php", get(extdata));
 
 		let game_router = Router::new()
@@ -234,12 +464,28 @@ impl App {
 			))
 			.route_layer(middleware::from_fn_with_state(shared_state.clone(), auth))
 			.route_layer(middleware::from_fn(xml_header_extractor))
-			.layer(Extension(self.db.clone()))
-			.layer(Extension(friendly_rooms));
-
-		let merged = app.merge(game_router);
+			.layer(Extension(self.storage.clone()))
+			.layer(Extension(friendly_rooms.clone()))
+			.layer(Extension(active_games.clone()))
+			.layer(Extension(games.clone()))
+			.layer(Extension(maps))
+			.layer(Extension(questions));
+
+		let mut merged = app.merge(game_router);
+		match &config().server.admin_token {
+			Some(token) => {
+				let admin = AdminState {
+					sessions: shared_state,
+					rooms: friendly_rooms,
+					active_games,
+					games,
+				};
+				merged = merged.merge(admin::router(token.clone(), admin));
+			}
+			None => info!("The admin API is disabled, set ADMIN_TOKEN to enable it"),
+		}
 
-		let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+		let listener = tokio::net::TcpListener::bind(address).await?;
 		axum::serve(listener, merged.into_make_service())
 			.await
 			.map_err(AppError::from)?;
@@ -283,6 +529,8 @@ async fn auth(
 ) -> Response {
 	use crate::channels::NO_CID;
 
+	let active_games = request.extensions().get::<ActiveGames>().cloned();
+	let storage = request.extensions().get::<SharedStorage>().cloned();
 	// get cid from above parsed xml header
 	if let Some(ax) = request
 		.extensions()
@@ -299,13 +547,26 @@ async fn auth(
 						quick_xml::de::from_str(&format!("<ROOT>{}</ROOT>", body_str)).unwrap();
 					match ser.msg_type {
 						crate::channels::command::request::CommandType::Login(login) => {
-							new_cid = if login.name == "felso" { 1 } else { 6 };
+							let Some(storage) = storage else {
+								error!("No storage to log in {}", login.name);
+								return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+							};
+							new_cid = match storage::login(&storage, &login.name).await {
+								Ok(id) => id,
+								Err(e) => {
+									error!("Failed to log in {}: {}", login.name, e);
+									return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+								}
+							};
 							let player_state = SharedPlayerState::new();
 							player_state.0.write().await.is_logged_in = true;
 							player_state.0.write().await.player_name = login.name.clone();
 							player_state.0.write().await.player_id = new_cid;
 							// todo we should log out the other player if exists
-							state.upsert_async(new_cid, player_state).await;
+							state.upsert_async(new_cid, player_state.clone()).await;
+							if let Some(active_games) = active_games {
+								rejoin_active_game(&active_games, &player_state).await;
+							}
 						}
 						_ => {
 							error!("Unauthorized command with NO_CID: {:?}", cmd);
@@ -330,6 +591,20 @@ async fn auth(
 	}
 }
 
+/// Puts the player back into their game in progress, if there is one
+async fn rejoin_active_game(active_games: &ActiveGames, player_state: &SharedPlayerState) {
+	let mut state = player_state.write_lock().await;
+	let Some(game) = active_games.get_async(&state.player_id).await else {
+		return;
+	};
+	let channels =
+		GamePlayerChannels::new(state.listen_channel.clone(), state.command_channel.clone());
+	match game.rejoin_player(state.player_id, channels).await {
+		Ok(()) => state.current_waithall = Waithall::Game,
+		Err(e) => error!("Unable to rejoin game: {}", e),
+	}
+}
+
 async fn set_session_for_player(
 	axum::extract::State(state): axum::extract::State<SharedState>,
 	mut request: Request,
@@ -394,3 +669,111 @@ where
 		Self(err.into())
 	}
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::triviador::game::TriviadorGame;
+	use crate::triviador::game_player_data::GamePlayerData;
+	use crate::triviador::game_rng::GameRng;
+	use crate::triviador::map::GameMap;
+	use crate::triviador::player_info::PlayerInfo;
+	use crate::triviador::question_provider::InMemoryQuestions;
+
+	pub(crate) fn test_game(storage: Option<SharedStorage>) -> SharedTrivGame {
+		let mut rng = StdRng::seed_from_u64(1);
+		let info = PlayerInfo {
+			p1_name: "Lajos".to_string(),
+			p2_name: "Bot".to_string(),
+			p3_name: "Bot".to_string(),
+			pd1: GamePlayerData::new_player(5, &mut rng),
+			pd2: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
+			pd3: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
+			you: "1,2,3".to_string(),
+			game_id: 1,
+			room: "1".to_string(),
+			rules: "0,0".to_string(),
+		};
+		let questions = Arc::new(InMemoryQuestions::generated(1, &mut rng));
+		SharedTrivGame::new(TriviadorGame::new_game(
+			info,
+			storage,
+			questions,
+			GameMap::hungary(),
+			GameRng::new(1),
+		))
+	}
+
+	#[tokio::test]
+	async fn crashed_games_are_unregistered() {
+		let games = GameRegistry::new();
+		let active_games = ActiveGames::new();
+		let friendly_rooms = FriendlyRooms::new();
+		let game = test_game(None);
+		let mut room = ActiveSepRoom::new(OpponentType::Player(5), "Lajos");
+		room.game = Some(game.arc_clone());
+		friendly_rooms.insert_async(1234, room).await.unwrap();
+
+		let (crash, wait) = tokio::sync::oneshot::channel::<()>();
+		games
+			.start(
+				1,
+				RunningGame::new(game.arc_clone(), vec![5, 6]).with_room(1234),
+				active_games.clone(),
+				friendly_rooms.clone(),
+				async move {
+					let _ = wait.await;
+					panic!("game crashed");
+				},
+			)
+			.await;
+		assert!(games.get(1).await.is_some());
+		assert!(active_games.get_async(&5).await.unwrap().ptr_eq(&game));
+		// the second player is already in a newer game
+		let newer = test_game(None);
+		active_games.insert_async(6, newer.arc_clone()).await;
+
+		crash.send(()).unwrap();
+		for _ in 0..100 {
+			if games.len() == 0 {
+				break;
+			}
+			tokio::task::yield_now().await;
+		}
+		assert!(games.get(1).await.is_none());
+		assert!(active_games.get_async(&5).await.is_none());
+		assert!(active_games.get_async(&6).await.unwrap().ptr_eq(&newer));
+		let room = friendly_rooms.get_async(&1234).await.unwrap();
+		assert!(room.get().game.is_none());
+	}
+
+	#[tokio::test]
+	async fn crashed_games_keep_their_replay() {
+		let storage = storage::connect("sqlite::memory:").await.unwrap();
+		let games = GameRegistry::new();
+		let game = test_game(Some(storage.clone()));
+		game.broadcast_public("<ROOT/>").await;
+
+		games
+			.start(
+				1,
+				RunningGame::new(game, vec![5]),
+				ActiveGames::new(),
+				FriendlyRooms::new(),
+				async { panic!("game crashed") },
+			)
+			.await;
+		for _ in 0..100 {
+			if storage.latest_replay(1).await.unwrap().is_some() {
+				break;
+			}
+			tokio::task::yield_now().await;
+		}
+		assert!(storage.latest_replay(1).await.unwrap().is_some());
+		assert!(storage.is_replay_viewer(1, 5).await.unwrap());
+		assert!(!storage.is_replay_viewer(1, 6).await.unwrap());
+	}
+}
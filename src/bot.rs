@@ -0,0 +1,2 @@
+pub(crate) mod strategy;
+pub(crate) mod territory;
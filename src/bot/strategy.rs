@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::territory::{Battlefield, attack_score, base_score, best_county, expansion_score};
+use crate::config::{BotDelay, config};
+use crate::triviador::areas::Areas;
+use crate::triviador::available_area::AvailableAreas;
+use crate::triviador::county::County;
+use crate::triviador::game_player_data::PlayerName;
+
+/// How well a bot plays, set per robot in friendly rooms
+#[derive(Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub(crate) enum BotDifficulty {
+	Easy,
+	#[default]
+	Normal,
+	Hard,
+}
+
+impl BotDifficulty {
+	/// Chance of answering a question correctly
+	fn answer_accuracy(self) -> f64 {
+		match self {
+			BotDifficulty::Easy => 0.4,
+			BotDifficulty::Normal => 0.6,
+			BotDifficulty::Hard => 0.85,
+		}
+	}
+
+	/// How far a tip can be from the correct value relative to it
+	fn tip_spread(self) -> f64 {
+		match self {
+			BotDifficulty::Easy => 0.4,
+			BotDifficulty::Normal => 0.2,
+			BotDifficulty::Hard => 0.07,
+		}
+	}
+
+	/// Chance of picking a random area instead of the best one
+	fn mistake_rate(self) -> f64 {
+		match self {
+			BotDifficulty::Easy => 0.4,
+			BotDifficulty::Normal => 0.15,
+			BotDifficulty::Hard => 0.0,
+		}
+	}
+
+	/// Scale of the configured bot delay, better bots answer faster
+	fn thinking_pace(self) -> f64 {
+		match self {
+			BotDifficulty::Easy => 1.25,
+			BotDifficulty::Normal => 1.0,
+			BotDifficulty::Hard => 0.5,
+		}
+	}
+
+	/// Shortest and longest thinking time in milliseconds
+	pub(crate) fn thinking_time(self, delay: &BotDelay) -> (u64, u64) {
+		let pace = self.thinking_pace();
+		let min = delay.min as f64 * pace;
+		let max = (delay.min + delay.spread) as f64 * pace;
+		(min as u64, max as u64)
+	}
+}
+
+impl TryFrom<u8> for BotDifficulty {
+	type Error = anyhow::Error;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		match value {
+			1 => Ok(BotDifficulty::Easy),
+			2 => Ok(BotDifficulty::Normal),
+			3 => Ok(BotDifficulty::Hard),
+			_ => bail!("Invalid bot difficulty: {}", value),
+		}
+	}
+}
+
+/// The decisions of a bot during the game
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BotStrategy {
+	difficulty: BotDifficulty,
+}
+
+impl BotStrategy {
+	/// Smallest spread of the tips, so small values are not guessed too easily
+	const MIN_TIP_SPREAD: f64 = 5.0;
+
+	pub(crate) fn new(difficulty: BotDifficulty) -> BotStrategy {
+		BotStrategy { difficulty }
+	}
+
+	#[allow(dead_code)]
+	pub(crate) fn difficulty(&self) -> BotDifficulty {
+		self.difficulty
+	}
+
+	/// Answer of a question with four options, random if the correct answer is unknown
+	pub(crate) fn answer(&self, good: Option<u8>, rng: &mut impl Rng) -> u8 {
+		let Some(good) = good.filter(|good| (1..=4).contains(good)) else {
+			return rng.gen_range(1..=4);
+		};
+		if rng.gen_bool(self.difficulty.answer_accuracy()) {
+			return good;
+		}
+		// one of the three bad answers
+		let bad = rng.gen_range(1..=3);
+		if bad >= good { bad + 1 } else { bad }
+	}
+
+	/// A tip near the correct value, closer ones are more likely
+	pub(crate) fn tip(&self, good: i32, rng: &mut impl Rng) -> i32 {
+		let spread =
+			(f64::from(good).abs() * self.difficulty.tip_spread()).max(Self::MIN_TIP_SPREAD);
+		// triangular noise between -spread and spread
+		let noise = (rng.r#gen::<f64>() + rng.r#gen::<f64>() - 1.0) * spread;
+		(f64::from(good) + noise).round() as i32
+	}
+
+	/// Time taken to answer a question or tip, centered between the shortest and longest time
+	pub(crate) fn answer_delay(&self, rng: &mut impl Rng) -> Duration {
+		let (min, max) = self.difficulty.thinking_time(&config().game.bot_delay);
+		let half = (max - min) / 2;
+		Duration::from_millis(min + rng.gen_range(0..=half) + rng.gen_range(0..=half))
+	}
+
+	/// Base far away from the opponents
+	pub(crate) fn pick_base(
+		&self,
+		areas: &Areas,
+		available: &AvailableAreas,
+		player: PlayerName,
+		rng: &mut impl Rng,
+	) -> Option<County> {
+		self.pick(available, |county| base_score(areas, player, county), rng)
+	}
+
+	/// Free area which keeps the territory together, used while spreading and filling
+	pub(crate) fn pick_area(
+		&self,
+		areas: &Areas,
+		available: &AvailableAreas,
+		player: PlayerName,
+		rng: &mut impl Rng,
+	) -> Option<County> {
+		self.pick(
+			available,
+			|county| expansion_score(areas, player, county),
+			rng,
+		)
+	}
+
+	pub(crate) fn pick_attack(
+		&self,
+		battlefield: &Battlefield,
+		available: &AvailableAreas,
+		player: PlayerName,
+		rng: &mut impl Rng,
+	) -> Option<County> {
+		self.pick(
+			available,
+			|county| attack_score(battlefield, player, county),
+			rng,
+		)
+	}
+
+	fn pick(
+		&self,
+		available: &AvailableAreas,
+		score: impl Fn(&County) -> i32,
+		rng: &mut impl Rng,
+	) -> Option<County> {
+		if rng.gen_bool(self.difficulty.mistake_rate()) {
+			return available.choose(rng);
+		}
+		best_county(available, score, rng)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	use super::*;
+
+	#[test]
+	fn better_bots_answer_better() {
+		let mut rng = StdRng::seed_from_u64(42);
+		let correct = |difficulty, rng: &mut StdRng| {
+			let bot = BotStrategy::new(difficulty);
+			(0..1000).filter(|_| bot.answer(Some(3), rng) == 3).count()
+		};
+		let easy = correct(BotDifficulty::Easy, &mut rng);
+		let hard = correct(BotDifficulty::Hard, &mut rng);
+		assert!(easy < hard, "easy: {}, hard: {}", easy, hard);
+
+		let bot = BotStrategy::new(BotDifficulty::Easy);
+		assert!((0..100).all(|_| (1..=4).contains(&bot.answer(Some(4), &mut rng))));
+		assert!((0..100).all(|_| (1..=4).contains(&bot.answer(None, &mut rng))));
+	}
+
+	#[test]
+	fn tips_are_near_the_correct_value() {
+		let mut rng = StdRng::seed_from_u64(42);
+		let bot = BotStrategy::new(BotDifficulty::Hard);
+		for _ in 0..100 {
+			assert!((bot.tip(1000, &mut rng) - 1000).abs() <= 70);
+			assert!((bot.tip(3, &mut rng) - 3).abs() <= 5);
+		}
+	}
+
+	#[test]
+	fn delays_fit_the_thinking_time() {
+		let mut rng = StdRng::seed_from_u64(42);
+		for difficulty in [
+			BotDifficulty::Easy,
+			BotDifficulty::Normal,
+			BotDifficulty::Hard,
+		] {
+			let (min, max) = difficulty.thinking_time(&config().game.bot_delay);
+			let delay = BotStrategy::new(difficulty).answer_delay(&mut rng);
+			assert!((min..=max).contains(&(delay.as_millis() as u64)));
+		}
+	}
+}
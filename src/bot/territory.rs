@@ -0,0 +1,244 @@
+use std::collections::{HashSet, VecDeque};
+
+use rand::Rng;
+use rand::seq::IteratorRandom;
+
+use crate::triviador::areas::Areas;
+use crate::triviador::available_area::AvailableAreas;
+use crate::triviador::bases::Bases;
+use crate::triviador::county::County;
+use crate::triviador::game_player_data::PlayerName;
+use crate::triviador::player_points::PlayerPoints;
+
+/// What a bot looks at when it picks an area to attack
+pub(crate) struct Battlefield<'a> {
+	pub areas: &'a Areas,
+	pub bases: &'a Bases,
+	pub points: &'a PlayerPoints,
+	/// In the second half of the war the points leader is targeted
+	pub late_war: bool,
+}
+
+/// Bases are best far away from the opponents
+pub(crate) fn base_score(areas: &Areas, player: PlayerName, county: &County) -> i32 {
+	let opponents: HashSet<County> = areas
+		.get_areas()
+		.iter()
+		.filter(|(_, area)| area.owner != player && area.owner != PlayerName::Nobody)
+		.map(|(county, _)| *county)
+		.collect();
+	distance_to(areas, county, &opponents)
+		.map(i32::from)
+		.unwrap_or(areas.map().counties().len() as i32)
+}
+
+/// Counties next to own areas keep the territory contiguous, among them the ones with more free
+/// neighbours leave more room to grow
+pub(crate) fn expansion_score(areas: &Areas, player: PlayerName, county: &County) -> i32 {
+	areas
+		.map()
+		.neighbours(county)
+		.filter_map(|neighbour| areas.get_area(neighbour))
+		.map(|area| match area.owner {
+			owner if owner == player => 10,
+			PlayerName::Nobody => 1,
+			_ => 0,
+		})
+		.sum()
+}
+
+/// Valuable and weakly defended areas are attacked first, castles with every tower standing are
+/// only attacked when the bot is ahead
+pub(crate) fn attack_score(battlefield: &Battlefield, player: PlayerName, county: &County) -> i32 {
+	let Some(area) = battlefield.areas.get_area(county) else {
+		return i32::MIN;
+	};
+	let mut score = i32::from(area.get_value().get_points() / 100);
+	if area.is_fortress() {
+		score -= 4;
+	}
+	if area.is_castle() {
+		let towers = battlefield
+			.bases
+			.get_base(&area.owner)
+			.map_or(0, |base| base.tower_count());
+		if towers == 3 && !is_ahead(battlefield, player) {
+			score -= 100;
+		} else {
+			score += i32::from(3 - towers.min(3)) * 5;
+		}
+	}
+	if battlefield.late_war && points_leader(battlefield, player) == Some(area.owner) {
+		score += 6;
+	}
+	score
+}
+
+/// The best county by the score, equally good ones are chosen randomly
+pub(crate) fn best_county(
+	available: &AvailableAreas,
+	score: impl Fn(&County) -> i32,
+	rng: &mut impl Rng,
+) -> Option<County> {
+	let counties = available.sorted();
+	let best = counties.iter().map(&score).max()?;
+	counties
+		.iter()
+		.filter(|county| score(county) == best)
+		.choose(rng)
+		.copied()
+}
+
+/// Steps needed from the county to the closest target
+fn distance_to(areas: &Areas, from: &County, targets: &HashSet<County>) -> Option<u8> {
+	let mut visited = HashSet::from([*from]);
+	let mut queue = VecDeque::from([(*from, 0)]);
+	while let Some((county, distance)) = queue.pop_front() {
+		if targets.contains(&county) {
+			return Some(distance);
+		}
+		for neighbour in areas.map().neighbours(&county) {
+			if visited.insert(*neighbour) {
+				queue.push_back((*neighbour, distance + 1));
+			}
+		}
+	}
+	None
+}
+
+fn opponents(battlefield: &Battlefield, player: PlayerName) -> HashSet<PlayerName> {
+	battlefield
+		.areas
+		.get_areas()
+		.values()
+		.map(|area| area.owner)
+		.filter(|owner| *owner != player && *owner != PlayerName::Nobody)
+		.collect()
+}
+
+fn is_ahead(battlefield: &Battlefield, player: PlayerName) -> bool {
+	let own = battlefield.points.get_player_points(&player);
+	opponents(battlefield, player)
+		.iter()
+		.all(|opponent| battlefield.points.get_player_points(opponent) < own)
+}
+
+/// The opponent with the most points
+fn points_leader(battlefield: &Battlefield, player: PlayerName) -> Option<PlayerName> {
+	opponents(battlefield, player)
+		.into_iter()
+		.max_by_key(|opponent| {
+			(
+				battlefield.points.get_player_points(opponent),
+				*opponent as u8,
+			)
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::triviador::county::hungary;
+	use crate::triviador::map::GameMap;
+
+	#[test]
+	fn base_far_from_opponents() {
+		// Player2 has a castle in Szabolcs-Szatmár-Bereg
+		let areas =
+			Areas::parse(GameMap::hungary(), "00000000000000000000000000000012000000").unwrap();
+		let available = AvailableAreas::get_base_areas(&areas, PlayerName::Player1);
+		let mut rng = StdRng::seed_from_u64(42);
+		let base = best_county(
+			&available,
+			|county| base_score(&areas, PlayerName::Player1, county),
+			&mut rng,
+		);
+		assert!(matches!(base, Some(hungary::ZALA) | Some(hungary::VAS)));
+	}
+
+	#[test]
+	fn area_keeps_territory_contiguous() {
+		// Player1 owns Pest and Nógrád
+		let areas =
+			Areas::parse(GameMap::hungary(), "11310000000000000000000000000000000000").unwrap();
+		let available = AvailableAreas::from(vec![hungary::HEVES, hungary::BACS_KISKUN]);
+		let mut rng = StdRng::seed_from_u64(42);
+		let area = best_county(
+			&available,
+			|county| expansion_score(&areas, PlayerName::Player1, county),
+			&mut rng,
+		);
+		assert_eq!(area, Some(hungary::HEVES));
+	}
+
+	#[test]
+	fn attack_avoids_full_castles_unless_ahead() {
+		// Player2 has a castle in Nógrád, Heves is a 200 point area of Player3
+		let areas =
+			Areas::parse(GameMap::hungary(), "11124300000000000000000000000000000000").unwrap();
+		let bases = Bases::from_str("010203").unwrap();
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player1, 1000);
+		points.set_player_points(&PlayerName::Player2, 1200);
+		points.set_player_points(&PlayerName::Player3, 800);
+		let available = AvailableAreas::from(vec![hungary::NOGRAD, hungary::HEVES]);
+		let mut rng = StdRng::seed_from_u64(42);
+
+		let mut battlefield = Battlefield {
+			areas: &areas,
+			bases: &bases,
+			points: &points,
+			late_war: false,
+		};
+		let attack = |battlefield: &Battlefield, rng: &mut StdRng| {
+			best_county(
+				&available,
+				|county| attack_score(battlefield, PlayerName::Player1, county),
+				rng,
+			)
+		};
+		assert_eq!(attack(&battlefield, &mut rng), Some(hungary::HEVES));
+
+		let leading = {
+			let mut points = points.clone();
+			points.set_player_points(&PlayerName::Player1, 2000);
+			points
+		};
+		battlefield.points = &leading;
+		assert_eq!(attack(&battlefield, &mut rng), Some(hungary::NOGRAD));
+	}
+
+	#[test]
+	fn late_war_targets_the_leader() {
+		// Nógrád is a 300 point area of Player2, Heves is a 400 point area of Player3
+		let areas =
+			Areas::parse(GameMap::hungary(), "11322300000000000000000000000000000000").unwrap();
+		let bases = Bases::from_str("010203").unwrap();
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player2, 1500);
+		points.set_player_points(&PlayerName::Player3, 900);
+		let available = AvailableAreas::from(vec![hungary::NOGRAD, hungary::HEVES]);
+		let mut rng = StdRng::seed_from_u64(42);
+
+		let mut battlefield = Battlefield {
+			areas: &areas,
+			bases: &bases,
+			points: &points,
+			late_war: false,
+		};
+		let score = |battlefield: &Battlefield, county: &County| {
+			attack_score(battlefield, PlayerName::Player1, county)
+		};
+		assert!(score(&battlefield, &hungary::HEVES) > score(&battlefield, &hungary::NOGRAD));
+		battlefield.late_war = true;
+		assert_eq!(
+			best_county(&available, |county| score(&battlefield, county), &mut rng),
+			Some(hungary::NOGRAD)
+		);
+	}
+}
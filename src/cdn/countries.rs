@@ -1,9 +1,17 @@
 use serde::{Deserialize, Serialize};
 
 use crate::cdn::countries::hungary::flags::HunFlags;
+use crate::triviador::map::GameMap;
 
 pub mod hungary;
 
+/// Query of `client_countries.php`
+#[derive(Deserialize, Debug)]
+pub struct CountriesRequest {
+	/// Name of the map, the default map if missing
+	pub map: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CountriesResponse {
 	pub error: String,
@@ -20,4 +28,69 @@ pub struct CountriesData {
 #[derive(Serialize, Deserialize, Debug)]
 pub enum FlagIds {
 	Hungarian(HunFlags),
+	/// Flag code of an area defined in a map file which the client doesn't know
+	Code(String),
+}
+
+impl FlagIds {
+	/// The Hungarian flag if the client knows the code
+	fn from_code(code: &str) -> FlagIds {
+		match HunFlags::from_code(code) {
+			Some(flag) => FlagIds::Hungarian(flag),
+			None => FlagIds::Code(code.to_string()),
+		}
+	}
+}
+
+impl CountriesResponse {
+	/// The areas of the map with their flags
+	pub(crate) fn from_map(map: &GameMap) -> CountriesResponse {
+		CountriesResponse {
+			error: "0".to_string(),
+			data: map
+				.areas()
+				.iter()
+				.map(|area| CountriesData {
+					id: FlagIds::from_code(&area.flag),
+					name: area.name.clone(),
+					description: area.description.clone(),
+				})
+				.collect(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::emulator::Emulator;
+
+	#[test]
+	fn default_map_keeps_the_baseline_format() {
+		let baseline = serde_json::to_value(CountriesResponse::emulate()).unwrap();
+		let response =
+			serde_json::to_value(CountriesResponse::from_map(&GameMap::hungary())).unwrap();
+		assert_eq!(response["error"], baseline["error"]);
+
+		let data = response["data"].as_array().unwrap();
+		assert_eq!(data.len(), 19);
+		let szabolcs = data
+			.iter()
+			.find(|area| area["name"] == "Szabolcs-Szatmár-Bereg")
+			.unwrap();
+		assert_eq!(szabolcs["id"], baseline["data"][0]["id"]);
+		assert_eq!(szabolcs["id"], serde_json::json!({"Hungarian": "sz"}));
+		for area in data {
+			assert_eq!(
+				area.as_object().unwrap().keys().collect::<Vec<_>>(),
+				baseline["data"][0]
+					.as_object()
+					.unwrap()
+					.keys()
+					.collect::<Vec<_>>()
+			);
+			assert!(area["id"]["Hungarian"].is_string());
+			assert!(!area["description"].as_str().unwrap().is_empty());
+		}
+	}
 }
@@ -58,6 +58,13 @@ pub mod flags {
 		#[serde(rename = "--")]
 		Unknown2,
 	}
+
+	impl HunFlags {
+		/// The flag of a code like `pe`
+		pub(crate) fn from_code(code: &str) -> Option<HunFlags> {
+			serde_json::from_value(serde_json::Value::String(code.to_string())).ok()
+		}
+	}
 }
 
 impl Emulator for CountriesResponse {
@@ -3,10 +3,11 @@ use serde::{Deserialize, Serialize};
 pub mod request {
 	use serde::{Deserialize, Serialize};
 
-	use crate::channels::command::AreaSelection;
+	use crate::channels::command::{AreaSelection, ReplayRequest};
 	use crate::login_screen::LoginXML;
 	use crate::menu::friend_list::external_data::ExtDataRequest;
 	use crate::triviador::question::{PlayerTipResponse, SelfAnswer};
+	use crate::triviador::tip_help::HelpRequest;
 	use crate::village::start::friendly_game::{
 		AddFriendlyRoom, ExitCurrentRoom, ReqFriendlyRoom, StartFriendlyRoom,
 	};
@@ -59,6 +60,12 @@ pub mod request {
 		QuestionAnswer(SelfAnswer),
 		#[serde(rename = "TIP")]
 		PlayerTipResponse(PlayerTipResponse),
+		#[serde(rename = "USEHELP")]
+		UseHelp(HelpRequest),
+		#[serde(rename = "SPECTATE")]
+		Spectate(ReqFriendlyRoom),
+		#[serde(rename = "REPLAY")]
+		Replay(ReplayRequest),
 	}
 }
 
@@ -68,6 +75,15 @@ pub struct AreaSelection {
 	pub area: u8,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplayRequest {
+	#[serde(rename = "@GAMEID")]
+	pub game_id: u32,
+	/// Playback speed multiplier, 1 is the original pace
+	#[serde(rename = "@SPEED")]
+	pub speed: Option<u8>,
+}
+
 pub mod response {
 	use serde::{Deserialize, Serialize};
 	use serde_with::skip_serializing_none;
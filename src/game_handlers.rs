@@ -1,8 +1,9 @@
-use std::time::Duration;
-
-use tokio::time::timeout;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
+use crate::config::config;
+use crate::triviador::available_area::AvailableAreas;
+use crate::triviador::cmd::Cmd;
+use crate::triviador::county::County;
 use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::users::ServerCommand;
@@ -13,49 +14,69 @@ pub(super) mod battle_handler;
 pub(super) mod endscreen_handler;
 pub(super) mod fill_remaining_handler;
 pub(super) mod question_handler;
+pub(crate) mod replay_handler;
 pub(super) mod s_game;
 pub(super) mod server_game_handler;
+pub(super) mod tiebreaker_handler;
 
 // todo move this over to game.rs
 
 /// Waits for the game to be ready for a player by listening for a `Ready` command
-/// Uses read lock with 10s timeout
+/// Uses read lock with the configured ready timeout, after too many timeouts in a row the player
+/// is considered disconnected
 pub(crate) async fn wait_for_game_ready(receiver: &SharedTrivGame, player: &PlayerName) {
-	let readgame = receiver.read().await;
-	let utils = readgame.utils.clone();
-	drop(readgame);
-	if let Some(channels) = &utils.get_player(player).unwrap().get_player_channels() {
-		let timeout_duration = Duration::from_secs(10);
-
-		let result = timeout(timeout_duration, async {
-			let mut command = channels
-				.command_channel
-				.recv_message()
-				.await
-				.expect("Failed to receive command");
-			while !command.variant_eq(&ServerCommand::Ready) {
-				command = channels
-					.command_channel
-					.recv_message()
-					.await
-					.expect("Failed to receive command");
-			}
-		})
+	let timeout_duration = config().game.ready_timeout();
+	let ready = receiver
+		.loop_recv_command(player, ServerCommand::Ready, timeout_duration)
 		.await;
 
-		if result.is_err() {
-			error!(
-				"Timeout waiting for Ready command from player: {:?}",
-				player
-			);
-			trace!("State: {:?}", receiver.read().await.state.game_state);
-		}
-		// channels.command_channel.clear();
-	} else {
-		error!("Channels not found for player: {:?}", player);
+	if let Err(e) = &ready {
+		error!("Player {:?} is not ready: {}", player, e);
+		trace!("State: {:?}", receiver.read().await.state.game_state);
+	}
+
+	let mut game = receiver.write().await;
+	let Some(info) = game.utils.get_player_mut(player) else {
+		return;
+	};
+	if ready.is_ok() {
+		info.ready_received();
+	} else if info.ready_missed() {
+		drop(game);
+		receiver.player_disconnected(player).await;
 	}
 }
 
+/// Waits for the area selected by a player until the select deadline passes.
+/// If the player doesn't answer in time or the selected area is not available, a random
+/// available area is chosen for them. Returns `None` only if there is nothing to choose from.
+pub(crate) async fn recv_area_selection(
+	game: &SharedTrivGame,
+	player: &PlayerName,
+	available: &AvailableAreas,
+) -> Option<County> {
+	let deadline = Cmd::deadline(Cmd::select_timeout());
+	let selection = game
+		.loop_recv_command(player, ServerCommand::SelectArea(0), deadline)
+		.await;
+	if let Some(info) = game.write().await.utils.get_player_mut(player) {
+		info.cmd_answered();
+	}
+	match selection {
+		Ok(ServerCommand::SelectArea(val)) => match County::try_from(val) {
+			Ok(county) if available.counties().contains(&county) => return Some(county),
+			_ => warn!("Player {:?} selected an unavailable area: {}", player, val),
+		},
+		Ok(command) => warn!("Invalid command: {:?}", command),
+		Err(e) => warn!("No area selected by player {:?}: {}", player, e),
+	}
+
+	let mut rng = game.rng().await;
+	let county = available.choose(&mut rng);
+	trace!("Random area selected for {:?}: {:?}", player, county);
+	county
+}
+
 /// Sends the common game state to a player
 /// Uses write lock
 pub(crate) async fn send_player_commongame(game: &SharedTrivGame, player: &PlayerName) {
@@ -66,13 +87,16 @@ pub(crate) async fn send_player_commongame(game: &SharedTrivGame, player: &Playe
 		.unwrap()
 		.get_cmd()
 		.clone();
+	commanded.state.set_shield_mission(player);
 	let xml = quick_xml::se::to_string(&commanded).unwrap();
-	game.send_xml_channel(player, xml).await.unwrap();
+	if let Err(e) = game.send_xml_channel(player, xml).await {
+		warn!("Failed to send game state to player {:?}: {}", player, e);
+	}
 	drop(commanded);
 	game.write()
 		.await
 		.utils
 		.get_player_mut(player)
 		.unwrap()
-		.set_cmd(None);
+		.cmd_sent();
 }
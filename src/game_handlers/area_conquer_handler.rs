@@ -1,8 +1,7 @@
-use rand::SeedableRng;
-use rand::prelude::{IteratorRandom, StdRng};
 use tracing::{error, trace};
 
 use crate::game_handlers::question_handler::{QuestionHandler, QuestionHandlerType};
+use crate::game_handlers::recv_area_selection;
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::cmd::Cmd;
 use crate::triviador::county::County;
@@ -10,7 +9,6 @@ use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::game_state::GameState;
 use crate::triviador::round_info::RoundInfo;
-use crate::users::ServerCommand;
 
 // invisible
 // Setup,
@@ -87,7 +85,7 @@ impl AreaConquerHandler {
 			Cmd::set_player_cmd(
 				self.game.arc_clone(),
 				&active_player,
-				Some(Cmd::select_command(available, 90)),
+				Some(Cmd::select_command(available, Cmd::select_timeout())),
 			)
 			.await;
 		}
@@ -108,28 +106,27 @@ impl AreaConquerHandler {
 			.unwrap()
 			.is_player()
 		{
-			if let ServerCommand::SelectArea(val) = self
-				.game
-				.recv_command_channel(&active_player)
-				.await
-				.unwrap()
+			let available = self.game.read().await.state.available_areas.clone();
+			if let Some(county) = recv_area_selection(&self.game, &active_player, &available).await
+				&& let Err(e) = self.new_area_selected(county.id(), active_player).await
 			{
-				self.new_area_selected(val, active_player).await.unwrap();
-			} else {
-				error!("Invalid command");
+				error!("Failed to select area: {}", e);
 			}
 			trace!("command received");
 		} else {
 			let readgame = self.game.read().await;
 			let areas = readgame.state.areas_info.clone();
 			let selection = readgame.state.selection.clone();
+			let bot = readgame.utils.get_player(&active_player).unwrap().bot();
 			drop(readgame);
 			let available_areas =
 				AvailableAreas::get_conquerable_areas(&areas, &selection, active_player);
 
-			let mut rng = StdRng::from_entropy();
-			let random_area = available_areas.counties().iter().choose(&mut rng).unwrap();
-			self.new_area_selected(*random_area as u8, active_player)
+			let mut rng = self.game.rng().await;
+			let area = bot
+				.pick_area(&areas, &available_areas, active_player, &mut rng)
+				.unwrap();
+			self.new_area_selected(area.id(), active_player)
 				.await
 				.unwrap();
 		}
@@ -142,10 +139,13 @@ impl AreaConquerHandler {
 	}
 
 	pub(super) async fn question(&self) {
+		let game = self.game.read().await;
+		let players = game.utils.retain_players(game.state.remaining_players());
+		drop(game);
 		let mut qh = QuestionHandler::new(
 			self.game.arc_clone(),
 			QuestionHandlerType::AreaConquer,
-			self.game.read().await.utils.clone(),
+			players,
 		)
 		.await;
 		qh.handle_all().await;
@@ -1,7 +1,6 @@
-use rand::SeedableRng;
-use rand::prelude::{IteratorRandom, StdRng};
-use tracing::{trace, warn};
+use tracing::trace;
 
+use crate::game_handlers::recv_area_selection;
 use crate::triviador::areas::Area;
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::bases::{Base, Bases};
@@ -11,7 +10,6 @@ use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::game_state::GameState;
 use crate::triviador::round_info::RoundInfo;
-use crate::users::ServerCommand;
 
 // Announcement,
 // StartSelection,
@@ -63,7 +61,9 @@ impl BaseHandler {
 		self.game.send_to_all_active().await;
 		self.game.wait_for_all_active().await;
 		trace!("Base select announcement game ready");
-		self.game.write().await.state.available_areas = AvailableAreas::all_counties();
+		let mut game = self.game.write().await;
+		game.state.available_areas = AvailableAreas::all_counties(game.state.areas_info.map());
+		drop(game);
 	}
 
 	pub(super) async fn start_selection(&self) {
@@ -92,7 +92,7 @@ impl BaseHandler {
 			Cmd::set_player_cmd(
 				self.game.arc_clone(),
 				&active_player,
-				Some(Cmd::select_command(available, 90)),
+				Some(Cmd::select_command(available, Cmd::select_timeout())),
 			)
 			.await;
 		}
@@ -116,20 +116,24 @@ impl BaseHandler {
 			let areas = self.game.read().await.state.areas_info.clone();
 			let available = AvailableAreas::get_base_areas(&areas, active_player);
 			self.game.write().await.state.available_areas = available.clone();
-			let mut rng = StdRng::from_entropy();
-			let random_area = available.counties().iter().choose(&mut rng).unwrap();
-			self.new_base_selected(*random_area as u8, active_player)
-				.await;
+			let bot = self
+				.game
+				.read()
+				.await
+				.utils
+				.get_player(&active_player)
+				.unwrap()
+				.bot();
+			let mut rng = self.game.rng().await;
+			if let Some(base) = bot.pick_base(&areas, &available, active_player, &mut rng) {
+				self.new_base_selected(base.id(), active_player).await;
+			}
 		} else {
 			self.game.write().await.cmd = None;
-			let command = self.game.recv_command_channel(&active_player).await;
-			match command.unwrap() {
-				ServerCommand::SelectArea(val) => {
-					self.new_base_selected(val, active_player).await;
-				}
-				_ => {
-					warn!("Invalid command");
-				}
+			let available = self.game.read().await.state.available_areas.clone();
+			if let Some(county) = recv_area_selection(&self.game, &active_player, &available).await
+			{
+				self.new_base_selected(county.id(), active_player).await;
 			}
 		}
 		self.game.write().await.state.game_state = GameState {
@@ -1,9 +1,9 @@
-use rand::SeedableRng;
-use rand::prelude::{IteratorRandom, StdRng};
 use tracing::{trace, warn};
 
 use super::question_handler::{TipHandler, TipHandlerType};
+use crate::bot::territory::Battlefield;
 use crate::game_handlers::question_handler::{QuestionHandler, QuestionHandlerType};
+use crate::game_handlers::recv_area_selection;
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::cmd::Cmd;
 use crate::triviador::county::County;
@@ -11,7 +11,6 @@ use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::game_state::GameState;
 use crate::triviador::question::QuestionAnswerResult;
-use crate::users::ServerCommand;
 
 // Phases
 //
@@ -30,6 +29,10 @@ use crate::users::ServerCommand;
 // OptionalTipQuestion,
 // 4,1,15
 // DestroyTower,
+// 4,1,17
+// BuildTower,
+// 4,1,19
+// AddFortress,
 // 4,1,21
 // SendUpdatedState,
 
@@ -74,6 +77,7 @@ impl BattleHandler {
 				self.castle_battle_decision().await;
 			}
 		}
+		self.game.write().await.state.track_missions();
 
 		self.send_updated_state().await;
 	}
@@ -85,7 +89,9 @@ impl BattleHandler {
 			round: 1,
 			phase: 0,
 		};
-		write_game.state.available_areas = AvailableAreas::all_counties();
+		write_game.state.available_areas =
+			AvailableAreas::all_counties(write_game.state.areas_info.map());
+		write_game.state.track_missions();
 	}
 
 	pub(super) async fn announcement(&self) {
@@ -120,7 +126,7 @@ impl BattleHandler {
 			Cmd::set_player_cmd(
 				self.game.arc_clone(),
 				&active_player,
-				Some(Cmd::select_command(available, 90)),
+				Some(Cmd::select_command(available, Cmd::select_timeout())),
 			)
 			.await;
 		}
@@ -141,17 +147,13 @@ impl BattleHandler {
 		{
 			Cmd::set_player_cmd(self.game.arc_clone(), &active_player, None).await;
 
-			match self
-				.game
-				.recv_command_channel(&active_player)
-				.await
-				.unwrap()
-			{
-				ServerCommand::SelectArea(val) => {
-					self.new_area_selected(val, active_player).await;
+			let available = self.game.read().await.state.available_areas.clone();
+			match recv_area_selection(&self.game, &active_player, &available).await {
+				Some(county) => {
+					self.new_area_selected(county.id(), active_player).await;
 					let readgame = self.game.read().await;
 					let areas_info = readgame.state.areas_info.clone();
-					let attacked = areas_info.get_area(&County::try_from(val).unwrap());
+					let attacked = areas_info.get_area(&county);
 					drop(readgame);
 					let attacked_player = attacked.map(|x| x.owner).unwrap_or(PlayerName::Nobody);
 					self.game.write().await.state.round_info.attacked_player =
@@ -174,22 +176,35 @@ impl BattleHandler {
 						AttackType::Castle
 					};
 				}
-				_ => {
-					warn!("Invalid command");
+				None => {
+					warn!("No area can be attacked by {:?}", active_player);
 				}
 			}
 		} else {
-			let available_areas = self.game.read().await.state.available_areas.clone();
-			let mut rng = StdRng::from_entropy();
-			let random_area = available_areas.counties().iter().choose(&mut rng).unwrap();
-
 			// Get the game state and find the owner of the selected area
 			let game_read = self.game.read().await;
-			let attacked = game_read
-				.state
+			let state = &game_read.state;
+			let battlefield = Battlefield {
+				areas: &state.areas_info,
+				bases: &state.base_info,
+				points: &state.players_points,
+				late_war: u16::from(state.game_state.round) * 2
+					> u16::from(game_read.rules.war_rounds),
+			};
+			let bot = game_read.utils.get_player(&active_player).unwrap().bot();
+			let mut rng = game_read.rng.fork();
+			let attacked_area = bot
+				.pick_attack(
+					&battlefield,
+					&state.available_areas,
+					active_player,
+					&mut rng,
+				)
+				.expect("Can't pick area to attack");
+			let attacked = state
 				.areas_info
-				.get_area(random_area)
-				.expect("Can't pick random area")
+				.get_area(&attacked_area)
+				.expect("Can't pick area to attack")
 				.clone();
 
 			drop(game_read);
@@ -217,11 +232,11 @@ impl BattleHandler {
 			game_write
 				.state
 				.selection
-				.add_selection(active_player, *random_area);
-			trace!("Random area selected: {:?}", random_area);
+				.add_selection(active_player, attacked_area);
+			trace!("Bot attacks area: {:?}", attacked_area);
 			drop(game_write);
 
-			self.new_area_selected(*random_area as u8, active_player)
+			self.new_area_selected(attacked_area.id(), active_player)
 				.await;
 		}
 		self.game.write().await.state.game_state.phase = 3;
@@ -272,6 +287,30 @@ impl BattleHandler {
 		self.game.wait_for_all_active().await;
 	}
 
+	/// A castle which holds a whole siege without losing a tower rebuilds one destroyed earlier
+	pub(super) async fn tower_build(&self) {
+		let mut write_game = self.game.write().await;
+		if !write_game.state.rebuild_tower(self.defender) {
+			return;
+		}
+		write_game.state.game_state.phase = 17;
+		drop(write_game);
+		self.game.send_to_all_active().await;
+		self.game.wait_for_all_active().await;
+	}
+
+	/// A successful defense of a basic area fortifies it
+	pub(super) async fn fortress_add(&self, county: County) {
+		let mut write_game = self.game.write().await;
+		if !write_game.state.fortify(county) {
+			return;
+		}
+		write_game.state.game_state.phase = 19;
+		drop(write_game);
+		self.game.send_to_all_active().await;
+		self.game.wait_for_all_active().await;
+	}
+
 	pub(super) async fn send_updated_state(&self) {
 		self.game.send_to_all_active().await;
 		self.game.wait_for_all_active().await;
@@ -310,14 +349,22 @@ impl BattleHandler {
 				.selection
 				.get_selection(&self.attacker)
 				.expect("Attacker has no areas selected");
-			if self.attacker == win {
-				let area = game_write
+			let area = game_write
+				.state
+				.areas_info
+				.get_area(&selected_county)
+				.expect("Area not found after conquering")
+				.clone();
+			if self.attacker == win && area.is_fortress() {
+				// the fortress absorbs the attack, the area stays with the defender
+				game_write
 					.state
-					.areas_info
-					.get_area(&selected_county)
-					.expect("Area not found after conquering")
-					.clone();
-
+					.absorb_attack(selected_county, self.attacker);
+				game_write
+					.state
+					.available_areas
+					.push_county(selected_county);
+			} else if self.attacker == win {
 				// Change points before upgrading (conquering) area
 				game_write.state.players_points.change_player_points(
 					&self.attacker,
@@ -346,6 +393,8 @@ impl BattleHandler {
 					.state
 					.available_areas
 					.push_county(selected_county);
+				drop(game_write);
+				self.fortress_add(selected_county).await;
 			};
 		}
 
@@ -353,6 +402,7 @@ impl BattleHandler {
 	}
 
 	pub(super) async fn castle_battle_decision(&mut self) {
+		let mut towers_lost = 0;
 		'castle_loop: loop {
 			let tower_count = self
 				.game
@@ -370,6 +420,7 @@ impl BattleHandler {
 					Some(winner) => {
 						if winner == self.attacker {
 							self.tower_destroy().await;
+							towers_lost += 1;
 						} else {
 							self.game
 								.write()
@@ -377,6 +428,9 @@ impl BattleHandler {
 								.state
 								.players_points
 								.change_player_points(&self.defender, 100);
+							if towers_lost == 0 {
+								self.tower_build().await;
+							}
 							break 'castle_loop;
 						}
 					}
@@ -389,7 +443,7 @@ impl BattleHandler {
 					.areas_info
 					.conquer_base_areas(self.defender, self.attacker)
 					.await;
-				game_write.state.eliminated_players.push(self.defender);
+				game_write.state.eliminate(self.defender);
 				game_write
 					.state
 					.players_points
@@ -1,9 +1,18 @@
+use std::cmp::Reverse;
+
 use serde::Serialize;
 use tokio_stream::StreamExt;
+use tracing::warn;
 
 use crate::{
 	emulator::Emulator,
-	triviador::{game::SharedTrivGame, game_state::GameState, triviador_state::TriviadorState},
+	triviador::{
+		game::SharedTrivGame,
+		game_player_data::{GamePlayerData, PlayerName},
+		game_state::GameState,
+		player_points::PlayerPoints,
+		triviador_state::TriviadorState,
+	},
 };
 
 pub(crate) struct EndScreenHandler {
@@ -16,33 +25,152 @@ impl EndScreenHandler {
 	}
 
 	pub(crate) async fn handle_all(&self) {
-		self.game.write().await.state.game_state = GameState {
+		let mut game = self.game.write().await;
+		game.state.game_state = GameState {
 			state: 15,
 			round: 0,
 			phase: 0,
 		};
+		game.state.track_missions();
+		drop(game);
 		let state = self.game.read().await.state.clone();
-		let gameover = Gameover::emulate();
+		let rewards = rewards(&state);
+		let gameover = Gameover::new(&rewards);
 		let division = Division::emulate();
 
+		let game = self.game.read().await;
+		let utils = game.utils.clone();
+		let players = game.players.clone();
+		drop(game);
+		let mut iter = utils.active_players_stream();
+		while let Some(player) = iter.next().await {
+			// the rewards and the shield mission are only sent to their owner
+			let mut es = EndscreenHandlerResponse {
+				state: state.clone(),
+				gameover: gameover.clone(),
+				division: division.clone(),
+			};
+			es.state.set_shield_mission(player);
+			let data = players
+				.as_ref()
+				.and_then(|players| players.get_player_data(player));
+			let reward = rewards.iter().find(|reward| reward.player == *player);
+			if let (Some(data), Some(reward)) = (data, reward) {
+				es.gameover = gameover.with_reward(reward, data);
+			}
+			let xml = quick_xml::se::to_string(&es).unwrap();
+			if let Err(e) = self.game.send_xml_channel(player, xml).await {
+				warn!("Failed to send end screen to player {:?}: {}", player, e);
+			}
+		}
 		let es = EndscreenHandlerResponse {
 			state,
 			gameover,
 			division,
 		};
-
-		let utils = self.game.read().await.utils.clone();
-		let mut iter = utils.active_players_stream();
-		while let Some(player) = iter.next().await {
-			self.game
-				.send_xml_channel(player, quick_xml::se::to_string(&es).unwrap())
-				.await
-				.unwrap();
+		self.game
+			.broadcast_public(&quick_xml::se::to_string(&es).unwrap())
+			.await;
+		if let Err(e) = self.game.save_result(&rewards).await {
+			warn!("Failed to save the result of the game: {}", e);
+		}
+		if let Err(e) = self.game.save_profiles(&rewards).await {
+			warn!("Failed to save the profiles: {}", e);
 		}
 		self.game.wait_for_all_active().await;
 	}
 }
 
+/// The players from first to last, players who left the game are placed last, equal points are
+/// decided by the tiebreaker ranking
+pub(crate) fn ranking(
+	points: &PlayerPoints,
+	forfeited: &[PlayerName],
+	tiebreak: &[PlayerName],
+) -> Vec<PlayerName> {
+	let mut ranking: Vec<PlayerName> = PlayerName::all().collect();
+	ranking.sort_by_key(|player| {
+		(
+			forfeited.contains(player),
+			Reverse(points.get_player_points(player)),
+			tiebreak
+				.iter()
+				.position(|ranked| ranked == player)
+				.unwrap_or(usize::MAX),
+		)
+	});
+	ranking
+}
+
+/// Base xp of the placings from first to last
+const PLACING_XP: [u32; 3] = [300, 200, 100];
+
+/// What a player earned in the game
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GameReward {
+	pub player: PlayerName,
+	/// 1 for the winner
+	pub placing: u8,
+	pub points: i16,
+	pub forfeited: bool,
+	/// Share of the points of every player
+	pub points_percent: u32,
+	pub placing_xp: u32,
+	pub points_xp: u32,
+	pub mission_xp: u32,
+	pub golds: u32,
+}
+
+impl GameReward {
+	pub(crate) fn xp(&self) -> u32 {
+		self.placing_xp + self.points_xp + self.mission_xp
+	}
+}
+
+/// The rewards of the players in seat order. The placing and the share of the points earn xp,
+/// a completed shield mission adds its golds and xp. Players who left the game earn nothing
+pub(crate) fn rewards(state: &TriviadorState) -> Vec<GameReward> {
+	let points = &state.players_points;
+	let ranking = ranking(points, &state.forfeited_players, &state.tiebreak_ranking);
+	let total: u32 = PlayerName::all()
+		.map(|player| points.get_player_points(&player).max(0) as u32)
+		.sum();
+	PlayerName::all()
+		.map(|player| {
+			let place = ranking
+				.iter()
+				.position(|ranked| *ranked == player)
+				.unwrap_or(ranking.len() - 1);
+			let player_points = points.get_player_points(&player);
+			let forfeited = state.forfeited_players.contains(&player);
+			let points_percent = (player_points.max(0) as u32 * 100)
+				.checked_div(total)
+				.unwrap_or(0);
+			let mut reward = GameReward {
+				player,
+				placing: place as u8 + 1,
+				points: player_points,
+				forfeited,
+				points_percent,
+				placing_xp: 0,
+				points_xp: 0,
+				mission_xp: 0,
+				golds: 0,
+			};
+			if !forfeited {
+				reward.placing_xp = PLACING_XP[place];
+				// all of the points earn as much as the first place
+				reward.points_xp = points_percent * 3;
+				if let Some(mission) = state.missions.get(&player) {
+					reward.mission_xp = mission.reward_xp();
+					reward.golds = mission.reward_golds();
+				}
+			}
+			reward
+		})
+		.collect()
+}
+
 #[derive(Serialize)]
 #[serde(rename = "ROOT")]
 pub(crate) struct EndscreenHandlerResponse {
@@ -54,7 +182,7 @@ pub(crate) struct EndscreenHandlerResponse {
 	pub division: Division,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Gameover {
 	#[serde(rename = "@PLACINGS")]
 	pub placings: String,
@@ -95,7 +223,7 @@ pub struct Gameover {
 	pub rl: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Division {
 	#[serde(rename = "@USERID")]
 	pub userid: String,
@@ -117,7 +245,7 @@ pub struct Division {
 	pub member: Vec<Member>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Member {
 	#[serde(rename = "@USERID")]
 	pub userid: String,
@@ -129,6 +257,37 @@ pub struct Member {
 	pub country: String,
 }
 
+impl Gameover {
+	/// The end screen without the rewards of a player, as the spectators see it
+	fn new(rewards: &[GameReward]) -> Gameover {
+		Gameover {
+			placings: rewards
+				.iter()
+				.map(|reward| reward.placing.to_string())
+				.collect(),
+			xp: "0,0,0,0".to_string(),
+			xppl: "0,0".to_string(),
+			xppp: "0,0".to_string(),
+			xpopp: "0,0".to_string(),
+			xpcw: "0,0".to_string(),
+			xpcw2: "0,0".to_string(),
+			golds: "0".to_string(),
+			..Gameover::emulate()
+		}
+	}
+
+	/// The end screen of the player, `data` is the player before the game
+	fn with_reward(&self, reward: &GameReward, data: &GamePlayerData) -> Gameover {
+		Gameover {
+			xp: format!("{},{},{},0", data.xp_points, reward.xp(), data.xp_level),
+			xppl: format!("{},{}", reward.placing, reward.placing_xp),
+			xppp: format!("{},{}", reward.points_percent, reward.points_xp),
+			golds: reward.golds.to_string(),
+			..self.clone()
+		}
+	}
+}
+
 impl Emulator for Gameover {
 	fn emulate() -> Self {
 		Gameover {
@@ -267,3 +426,78 @@ impl Emulator for Division {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::app::tests::test_game;
+	use crate::triviador::map::GameMap;
+	use crate::triviador::shield_mission::{MISSIONS, PlayerMission};
+
+	async fn state_with_points(points: [i16; 3]) -> TriviadorState {
+		let mut state = test_game(None).read().await.state.clone();
+		for (player, points) in PlayerName::all().zip(points) {
+			state.players_points.set_player_points(&player, points);
+		}
+		state
+	}
+
+	fn placings(state: &TriviadorState) -> String {
+		Gameover::new(&rewards(state)).placings
+	}
+
+	#[tokio::test]
+	async fn placings_by_points() {
+		let mut state = state_with_points([1200, 1800, 1000]).await;
+		assert_eq!(placings(&state), "213");
+		state.forfeited_players.push(PlayerName::Player2);
+		assert_eq!(placings(&state), "132");
+	}
+
+	#[tokio::test]
+	async fn placings_with_tiebreak() {
+		let mut state = state_with_points([1200, 1200, 1000]).await;
+		state.tiebreak_ranking = vec![PlayerName::Player2, PlayerName::Player1];
+		assert_eq!(placings(&state), "213");
+	}
+
+	#[tokio::test]
+	async fn rewards_come_from_the_result() {
+		let mut state = state_with_points([1200, 1800, 1000]).await;
+		let mut rng = StdRng::seed_from_u64(1);
+		let mut mission = PlayerMission::new(&MISSIONS[0], &GameMap::hungary(), &mut rng);
+		for _ in 0..3 {
+			mission.war_round_survived();
+		}
+		state.missions.insert(PlayerName::Player1, mission.clone());
+		state.missions.insert(PlayerName::Player3, mission);
+		state.forfeited_players.push(PlayerName::Player3);
+
+		let rewards = rewards(&state);
+		let first = &rewards[1];
+		assert_eq!((first.placing, first.points_percent), (1, 45));
+		assert_eq!(first.xp(), 300 + 135);
+		assert_eq!(first.golds, 0);
+		let second = &rewards[0];
+		assert_eq!(second.placing, 2);
+		assert_eq!(second.xp(), 200 + 90 + MISSIONS[0].reward_xp);
+		assert_eq!(second.golds, MISSIONS[0].reward_golds);
+		// leaving the game earns nothing, not even the completed mission
+		let forfeited = &rewards[2];
+		assert_eq!(forfeited.placing, 3);
+		assert_eq!((forfeited.xp(), forfeited.golds), (0, 0));
+
+		let data = GamePlayerData::new_player(5, &mut rng);
+		let gameover = Gameover::new(&rewards).with_reward(second, &data);
+		assert_eq!(
+			gameover.xp,
+			format!("{},340,{},0", data.xp_points, data.xp_level)
+		);
+		assert_eq!(gameover.xppl, "2,200");
+		assert_eq!(gameover.xppp, "30,90");
+		assert_eq!(gameover.golds, "300");
+	}
+}
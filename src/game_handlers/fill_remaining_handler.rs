@@ -1,9 +1,8 @@
-use rand::SeedableRng;
-use rand::prelude::{IteratorRandom, StdRng};
 use tracing::{trace, warn};
 
 use crate::game_handlers::question_handler::{TipHandler, TipHandlerType};
-use crate::triviador::areas::Area;
+use crate::game_handlers::recv_area_selection;
+use crate::triviador::areas::{Area, Areas};
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::cmd::Cmd;
 use crate::triviador::county::County;
@@ -11,7 +10,6 @@ use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::game_state::GameState;
 use crate::triviador::round_info::RoundInfo;
-use crate::users::ServerCommand;
 
 // invisible
 // Setup
@@ -41,6 +39,9 @@ impl FillRemainingHandler {
 			phase: 0,
 		};
 		write_game.state.round_info.mini_phase_num = 0;
+		// whatever spreading left free is filled
+		write_game.state.available_areas =
+			AvailableAreas::from(write_game.state.areas_info.free_counties());
 	}
 
 	pub(super) async fn announcement(&self) {
@@ -52,10 +53,19 @@ impl FillRemainingHandler {
 	}
 
 	pub(super) async fn tip_question(&mut self) {
-		// TODO fix this, not all players should be questioned
-		let players = self.game.arc_clone().read().await.utils.clone();
-		let mut th = TipHandler::new(self.game.arc_clone(), TipHandlerType::Fill, players).await;
-		self.winner = Some(th.handle_all().await);
+		let game = self.game.read().await;
+		let competitors = fill_competitors(&game.state.areas_info, &game.state.remaining_players());
+		let players = game.utils.retain_players(competitors.clone());
+		drop(game);
+		// a single competitor gets the county without a question, the others only watch the tip
+		self.winner = match competitors.as_slice() {
+			[only] => Some(*only),
+			_ => {
+				let mut th =
+					TipHandler::new(self.game.arc_clone(), TipHandlerType::Fill, players).await;
+				Some(th.handle_all().await)
+			}
+		};
 		self.game
 			.write()
 			.await
@@ -71,6 +81,12 @@ impl FillRemainingHandler {
 		game_writer.state.game_state.phase = 4;
 
 		let ri = game_writer.state.round_info.clone();
+		// the winner can only pick a free county next to their areas if there is one
+		game_writer.state.available_areas = AvailableAreas::get_conquerable_areas(
+			&game_writer.state.areas_info,
+			&game_writer.state.selection,
+			active_player,
+		);
 
 		game_writer.state.round_info = RoundInfo {
 			mini_phase_num: ri.mini_phase_num,
@@ -88,12 +104,11 @@ impl FillRemainingHandler {
 			.unwrap()
 			.is_player()
 		{
-			// let available = AvailableAreas::get_available(temp_pool, self.game_id).await;
 			let available = self.game.read().await.state.available_areas.clone();
 			Cmd::set_player_cmd(
 				self.game.arc_clone(),
 				&active_player,
-				Some(Cmd::select_command(available, 90)),
+				Some(Cmd::select_command(available, Cmd::select_timeout())),
 			)
 			.await;
 		}
@@ -114,30 +129,27 @@ impl FillRemainingHandler {
 			.unwrap()
 			.is_player()
 		{
-			match self
-				.game
-				.recv_command_channel(&active_player)
-				.await
-				.unwrap()
+			let available = self.game.read().await.state.available_areas.clone();
+			if let Some(county) = recv_area_selection(&self.game, &active_player, &available).await
+				&& let Err(e) = self.new_area_selected(county.id(), active_player).await
 			{
-				ServerCommand::SelectArea(val) => {
-					self.new_area_selected(val, active_player).await.unwrap();
-				}
-				_ => {
-					warn!("Invalid command");
-				}
+				warn!("Failed to select area: {}", e);
 			}
 		} else {
-			let areas = self.game.read().await.state.areas_info.clone();
-			let selection = self.game.read().await.state.selection.clone();
-			let available_areas =
-				AvailableAreas::get_conquerable_areas(&areas, &selection, active_player);
-
-			let mut rng = StdRng::from_entropy();
-			let random_area = available_areas.counties().iter().choose(&mut rng).unwrap();
-			self.new_area_selected(*random_area as u8, active_player)
-				.await
-				.unwrap();
+			let readgame = self.game.read().await;
+			let available_areas = readgame.state.available_areas.clone();
+			let areas = readgame.state.areas_info.clone();
+			let bot = readgame.utils.get_player(&active_player).unwrap().bot();
+			drop(readgame);
+			let mut rng = self.game.rng().await;
+			match bot.pick_area(&areas, &available_areas, active_player, &mut rng) {
+				Some(area) => {
+					if let Err(e) = self.new_area_selected(area.id(), active_player).await {
+						warn!("Failed to select area: {}", e);
+					}
+				}
+				None => warn!("No free county left for {:?}", active_player),
+			}
 		}
 		self.game.send_to_all_active().await;
 		self.game.wait_for_all_active().await;
@@ -173,3 +185,47 @@ impl FillRemainingHandler {
 		Ok(())
 	}
 }
+
+/// Players competing for the remaining counties are the ones with a free county next to their
+/// areas, if nobody has one every remaining player competes
+fn fill_competitors(areas: &Areas, players: &[PlayerName]) -> Vec<PlayerName> {
+	let competitors: Vec<PlayerName> = players
+		.iter()
+		.copied()
+		.filter(|player| {
+			!AvailableAreas::get_adjacent_free_areas(areas, *player)
+				.counties()
+				.is_empty()
+		})
+		.collect();
+	if competitors.is_empty() {
+		players.to_vec()
+	} else {
+		competitors
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::triviador::map::GameMap;
+
+	#[test]
+	fn only_adjacent_players_compete() {
+		// every county is taken except Pest (01) which only borders player 1 and player 2
+		let mut areas =
+			Areas::parse(GameMap::hungary(), "00414141424242434343434343434343434343").unwrap();
+		let all = [
+			PlayerName::Player1,
+			PlayerName::Player2,
+			PlayerName::Player3,
+		];
+		assert_eq!(
+			fill_competitors(&areas, &all),
+			vec![PlayerName::Player1, PlayerName::Player2]
+		);
+
+		areas = Areas::parse(GameMap::hungary(), "41414141424242434343434343434343434343").unwrap();
+		assert_eq!(fill_competitors(&areas, &all), all.to_vec());
+	}
+}
@@ -1,18 +1,21 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
-use rand::prelude::StdRng;
-use rand::{Rng, SeedableRng};
+use anyhow::bail;
 use tokio::sync::Mutex;
 use tokio_stream::StreamExt;
 use tracing::{error, trace, warn};
 
 use super::s_game::GamePlayerInfo;
+use crate::emulator::Emulator;
 use crate::triviador::areas::Area;
+use crate::triviador::cmd::Cmd;
 use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::question::{
 	Question, QuestionAnswerResult, QuestionStageResponse, TipInfo, TipQuestion, TipStageResponse,
 };
+use crate::triviador::tip_help::{TipHelp, TipHelpResponse};
 use crate::users::ServerCommand;
 
 // 2,1,4
@@ -80,10 +83,27 @@ impl QuestionHandler {
 				self.game.write().await.state.game_state.phase = 4;
 			}
 		}
-		let db = self.game.arc_clone().write().await.db.clone();
-		let q = Some(Question::get_from_db(&db).await);
+		let questions = self.game.read().await.questions.clone();
+		let mut rng = self.game.rng().await;
+		let q = Some(
+			questions
+				.choice_question(&mut rng)
+				.await
+				.unwrap_or_else(|err| {
+					error!(
+						"Failed to get a question, asking the default one: {:#}",
+						err
+					);
+					Question::emulate()
+				}),
+		);
 		self.answer = q.as_ref().unwrap().good;
 		let state = self.game.read().await.state.clone();
+		let mut public = QuestionStageResponse::new_question(state.clone(), q.clone().unwrap());
+		public.cmd = None;
+		self.game
+			.broadcast_public(&quick_xml::se::to_string(&public).unwrap())
+			.await;
 		let utils = self.game.read().await.utils.clone();
 		let iter = utils.active_players_stream();
 		futures::stream::StreamExt::for_each_concurrent(iter, None, |player| {
@@ -109,35 +129,43 @@ impl QuestionHandler {
 	async fn get_question_response(&mut self) {
 		trace!("get_question_response");
 		let answer_result = Arc::new(Mutex::new(self.answer_result.clone()));
+		let good = self.answer;
+		let bot_rngs = self
+			.game
+			.read()
+			.await
+			.rng
+			.fork_each(&self.question_players.players());
 		let iter = self.question_players.players_with_info_stream();
 		trace!("get_question_response iter: {:?}", self.question_players);
 		futures::stream::StreamExt::for_each_concurrent(iter, None, |(player, info)| {
 			let game = self.game.arc_clone();
 			let player = *player;
 			let answer_result = Arc::clone(&answer_result);
+			let mut rng = bot_rngs[&player].clone();
 			async move {
 				if info.is_player() {
+					let deadline = Cmd::deadline(Cmd::answer_timeout());
 					match game
-						.loop_recv_command(&player, ServerCommand::QuestionAnswer(0))
+						.loop_recv_command(&player, ServerCommand::QuestionAnswer(0), deadline)
 						.await
-						.unwrap()
 					{
-						ServerCommand::QuestionAnswer(ans) => {
+						Ok(ServerCommand::QuestionAnswer(ans)) => {
 							trace!("got_question_response: {:?}", player);
 							answer_result.lock().await.set_player_answer(&player, ans);
 						}
-						_ => {
-							error!("Unable to loop wait for player");
-						}
+						// no answer is recorded, which counts as a bad answer
+						Ok(command) => error!("Invalid command: {:?}", command),
+						Err(e) => warn!("No answer from player {:?}: {}", player, e),
 					}
 				} else {
-					tokio::time::sleep(std::time::Duration::from_millis(7331)).await; // Simulate delay for bots
-					let mut rng = StdRng::from_entropy();
-					let random_answer: u8 = rng.gen_range(1..=4);
+					let bot = info.bot();
+					tokio::time::sleep(bot.answer_delay(&mut rng)).await;
+					let bot_answer = bot.answer(good, &mut rng);
 					answer_result
 						.lock()
 						.await
-						.set_player_answer(&player, random_answer);
+						.set_player_answer(&player, bot_answer);
 				}
 			}
 		})
@@ -163,21 +191,19 @@ impl QuestionHandler {
 		}
 		// todo find a better way than cloning this, possible arc
 		let state = self.game.read().await.state.clone();
+		let xml = quick_xml::se::to_string(&QuestionStageResponse::new_answer_result(
+			state,
+			self.answer_result.clone(),
+		))
+		.unwrap();
 		let utils = self.game.read().await.utils.clone();
 		let mut iter = utils.active_players_stream();
 		while let Some(player) = iter.next().await {
-			self.game
-				.send_xml_channel(
-					player,
-					quick_xml::se::to_string(&QuestionStageResponse::new_answer_result(
-						state.clone(),
-						self.answer_result.clone(),
-					))
-					.unwrap(),
-				)
-				.await
-				.unwrap();
+			if let Err(e) = self.game.send_xml_channel(player, xml.clone()).await {
+				warn!("Failed to send answers to player {}: {}", player, e);
+			}
 		}
+		self.game.broadcast_public(&xml).await;
 		self.game.wait_for_all_active().await;
 	}
 
@@ -211,6 +237,21 @@ impl QuestionHandler {
 							.push_county(*selection.get_player_county(player).unwrap());
 					}
 				}
+				// phase 7 reorders the boxes and hides the rounds that won't be played
+				let mut write_game = self.game.write().await;
+				// the mini phase is the number of players who picked an area this round
+				let round = write_game.state.game_state.round as usize;
+				let picked = write_game
+					.state
+					.war_order
+					.as_ref()
+					.map_or(0, |wo| wo.round(round.saturating_sub(1)).len());
+				write_game.state.round_info.mini_phase_num = picked as u8;
+				let continues = write_game.state.spreading_continues();
+				if let Some(wo) = write_game.state.war_order.as_mut().filter(|_| !continues) {
+					wo.truncate(round);
+				}
+				drop(write_game);
 				self.game.send_to_all_active().await;
 			}
 			QuestionHandlerType::Battle => {
@@ -236,6 +277,7 @@ impl QuestionHandler {
 pub(crate) enum TipHandlerType {
 	Fill,
 	Battle,
+	Tiebreaker,
 }
 
 pub(crate) struct TipHandler {
@@ -243,6 +285,7 @@ pub(crate) struct TipHandler {
 	tip_players: GamePlayerInfo,
 	tip_handler_type: TipHandlerType,
 	tip_info: TipInfo,
+	question: String,
 	good: Option<i32>,
 }
 
@@ -257,6 +300,7 @@ impl TipHandler {
 			tip_players: players,
 			tip_handler_type: stage_type,
 			tip_info: TipInfo::new(),
+			question: String::new(),
 			good: None,
 		}
 	}
@@ -269,17 +313,33 @@ impl TipHandler {
 
 	async fn send_tip_request(&mut self) {
 		match self.tip_handler_type {
-			TipHandlerType::Fill => {
+			TipHandlerType::Fill | TipHandlerType::Tiebreaker => {
 				self.game.write().await.state.game_state.phase = 1;
 			}
 			TipHandlerType::Battle => {
 				self.game.write().await.state.game_state.phase = 10;
 			}
 		}
-		let db = self.game.arc_clone().write().await.db.clone();
-		let tq = TipQuestion::get_from_db(&db).await;
+		let questions = self.game.read().await.questions.clone();
+		let mut rng = self.game.rng().await;
+		let tq = questions
+			.tip_question(&mut rng)
+			.await
+			.unwrap_or_else(|err| {
+				error!(
+					"Failed to get a tip question, asking the default one: {:#}",
+					err
+				);
+				TipQuestion::emulate()
+			});
 		self.good = tq.good;
+		self.question = tq.question.clone();
 		let state = self.game.read().await.state.clone();
+		let mut public = TipStageResponse::new_tip_question(state.clone(), tq.clone());
+		public.cmd = None;
+		self.game
+			.broadcast_public(&quick_xml::se::to_string(&public).unwrap())
+			.await;
 		let utils = self.game.read().await.utils.clone();
 		let iter = utils.active_players_stream();
 		futures::stream::StreamExt::for_each_concurrent(iter, None, |player| {
@@ -304,37 +364,44 @@ impl TipHandler {
 	async fn get_tip_response(&mut self) {
 		let start = std::time::Instant::now();
 		let tip_info = Arc::new(Mutex::new(self.tip_info.clone()));
+		let good = self.good.unwrap();
+		let bot_rngs = self
+			.game
+			.read()
+			.await
+			.rng
+			.fork_each(&self.tip_players.players());
 
 		let iter = self.tip_players.players_with_info_stream();
 		futures::stream::StreamExt::for_each_concurrent(iter, None, |(player, info)| {
 			let game = self.game.arc_clone();
 			let tip_info = Arc::clone(&tip_info);
+			let question = &self.question;
 			let player = *player;
+			let mut rng = bot_rngs[&player].clone();
 
 			async move {
 				if info.is_player() {
-					match game
-						.loop_recv_command(&player, ServerCommand::TipAnswer(0))
-						.await
-					{
-						Ok(ServerCommand::TipAnswer(ans)) => {
+					match recv_tip_with_helps(&game, player, question, good).await {
+						Ok(ans) => {
 							tip_info.lock().await.add_player_tip(
 								player,
 								ans,
 								start.elapsed().as_secs_f32(),
 							);
 						}
-						_ => {
-							error!("Unable to loop wait for player");
+						Err(e) => {
+							// players without a tip are ranked last
+							warn!("No tip from player {:?}: {}", player, e);
 						}
 					}
 				} else {
-					tokio::time::sleep(std::time::Duration::from_millis(7331)).await; // Simulate delay for bots
-					let mut rng = StdRng::from_entropy();
-					let random_answer = rng.gen_range(1..100);
+					let bot = info.bot();
+					tokio::time::sleep(bot.answer_delay(&mut rng)).await;
+					let bot_tip = bot.tip(good, &mut rng);
 					tip_info.lock().await.add_player_tip(
 						player,
-						random_answer,
+						bot_tip,
 						start.elapsed().as_secs_f32(),
 					);
 				}
@@ -344,6 +411,8 @@ impl TipHandler {
 
 		// Update self.tip_info after collecting all results
 		self.tip_info = tip_info.lock().await.clone();
+		let tips = PlayerName::all().filter_map(|player| self.tip_info.get_player_tip(&player));
+		TipHelp::record_tips(&self.question, good, tips);
 	}
 
 	// todo cleanup
@@ -354,19 +423,21 @@ impl TipHandler {
 		self.game.write().await.state.game_state.phase += 2;
 
 		let state = self.game.read().await.state.clone();
-		let tip_stage_response =
-			TipStageResponse::new_tip_result(state.clone(), self.tip_info.clone(), good);
+		let tip_stage_response = TipStageResponse::new_tip_result(
+			state.clone(),
+			self.tip_info.clone(),
+			good,
+			&self.tip_players.players(),
+		);
+		let xml = quick_xml::se::to_string(&tip_stage_response).unwrap();
 		let utils = self.game.read().await.utils.clone();
 		let mut iter = utils.active_players_stream();
 		while let Some(player) = iter.next().await {
-			self.game
-				.send_xml_channel(
-					player,
-					quick_xml::se::to_string(&tip_stage_response).unwrap(),
-				)
-				.await
-				.unwrap();
+			if let Err(e) = self.game.send_xml_channel(player, xml.clone()).await {
+				warn!("Failed to send tip results to player {}: {}", player, e);
+			}
 		}
+		self.game.broadcast_public(&xml).await;
 		// Wait at least 1 second for players to receive the answer
 		tokio::join!(
 			self.game.wait_for_all_active(),
@@ -376,3 +447,85 @@ impl TipHandler {
 		winner.winner
 	}
 }
+
+/// Waits for the tip of a player, meanwhile the tip helps bought by the player are answered
+/// privately on their listen channel
+async fn recv_tip_with_helps(
+	game: &SharedTrivGame,
+	player: PlayerName,
+	question: &str,
+	good: i32,
+) -> anyhow::Result<i32> {
+	let used_now = Arc::new(Mutex::new(HashSet::new()));
+	let tip = game
+		.loop_recv_command_with(
+			&player,
+			ServerCommand::TipAnswer(0),
+			Cmd::deadline(Cmd::tip_timeout()),
+			|command| {
+				let game = game.arc_clone();
+				let question = question.to_string();
+				let used_now = Arc::clone(&used_now);
+				async move {
+					if let ServerCommand::UseHelp(help) = command {
+						let mut used_now = used_now.lock().await;
+						use_tip_help(&game, player, help, &question, good, &mut used_now).await?;
+					}
+					Ok(())
+				}
+			},
+		)
+		.await?;
+	match tip {
+		ServerCommand::TipAnswer(tip) => Ok(tip),
+		command => bail!("Invalid command: {:?}", command),
+	}
+}
+
+/// Sends the help to the player if they can buy it, every help can be used once per question
+async fn use_tip_help(
+	game: &SharedTrivGame,
+	player: PlayerName,
+	help: TipHelp,
+	question: &str,
+	good: i32,
+	used_now: &mut HashSet<TipHelp>,
+) -> anyhow::Result<()> {
+	if used_now.contains(&help) {
+		warn!(
+			"Player {} already used {:?} for this question",
+			player, help
+		);
+		return Ok(());
+	}
+	let response = match help {
+		TipHelp::Range => {
+			let mut rng = game.rng().await;
+			let (min, max) = TipHelp::range_hint(good, &mut rng);
+			TipHelpResponse::range(min, max)
+		}
+		TipHelp::Closest => match TipHelp::previous_closest(question) {
+			Some(closest) => TipHelpResponse::closest(closest),
+			None => {
+				warn!("No earlier tips for the question, {:?} can't be used", help);
+				return Ok(());
+			}
+		},
+	};
+	let mut write_game = game.write().await;
+	let paid = write_game
+		.players
+		.as_mut()
+		.and_then(|players| players.get_player_data_mut(&player))
+		.is_some_and(|data| data.spend_golds(help.cost()));
+	if !paid {
+		warn!("Player {} can't afford {:?}", player, help);
+		return Ok(());
+	}
+	write_game.state.used_helps.add(player, help);
+	drop(write_game);
+	used_now.insert(help);
+	trace!("Player {} used tip help {:?}", player, help);
+	game.send_xml_channel(&player, quick_xml::se::to_string(&response)?)
+		.await
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use anyhow::{Context, ensure};
+use tracing::{trace, warn};
+
+use crate::app::ListenPlayerChannel;
+use crate::config::config;
+use crate::storage::SharedStorage;
+use crate::triviador::replay::{ReplayEvent, ReplayLog};
+
+/// Loads the latest recorded replay of a game, only for users who played or watched it
+pub(crate) async fn load(
+	storage: &SharedStorage,
+	game_id: u32,
+	user_id: i32,
+) -> anyhow::Result<ReplayLog> {
+	ensure!(
+		storage.is_replay_viewer(game_id, user_id).await?,
+		"User {} neither played nor watched the game",
+		user_id
+	);
+	let events = storage
+		.latest_replay(game_id)
+		.await?
+		.context("No replay was recorded")?;
+	Ok(ReplayLog::from_json(&events)?)
+}
+
+/// Streams the recorded states through the listen channel, `speed` accelerates the original pace
+pub(crate) async fn play(log: ReplayLog, channel: ListenPlayerChannel, speed: u8) {
+	let speed = u64::from(speed.max(1));
+	let mut previous = 0;
+	for event in log.events() {
+		let ReplayEvent::State { at_ms, xml } = event else {
+			continue;
+		};
+		tokio::time::sleep(Duration::from_millis(
+			at_ms.saturating_sub(previous) / speed,
+		))
+		.await;
+		previous = at_ms;
+		match tokio::time::timeout(config().game.send_timeout(), channel.send_message(xml)).await {
+			Ok(Ok(())) => {}
+			_ => {
+				warn!("Replay viewer stopped listening");
+				return;
+			}
+		}
+	}
+	trace!("Replay finished");
+}
@@ -1,15 +1,18 @@
 use std::collections::HashMap;
 
-use rand::SeedableRng;
-use rand::prelude::{IteratorRandom, StdRng};
+use rand::prelude::IteratorRandom;
 use tokio_stream::{Stream, StreamExt};
 use tracing::{info, trace, warn};
 
+use super::endscreen_handler::EndScreenHandler;
 use crate::app::GamePlayerChannels;
+use crate::bot::strategy::BotStrategy;
+use crate::config::config;
 use crate::game_handlers::area_conquer_handler::AreaConquerHandler;
 use crate::game_handlers::base_handler::BaseHandler;
 use crate::game_handlers::battle_handler::BattleHandler;
 use crate::game_handlers::fill_remaining_handler::FillRemainingHandler;
+use crate::game_handlers::tiebreaker_handler::TiebreakerHandler;
 use crate::triviador::areas::Area;
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::cmd::Cmd;
@@ -17,49 +20,83 @@ use crate::triviador::game::SharedTrivGame;
 use crate::triviador::game_player_data::PlayerName;
 use crate::triviador::game_state::GameState;
 use crate::triviador::round_info::RoundInfo;
+use crate::triviador::shield_mission::PlayerMission;
 use crate::triviador::war_order::WarOrder;
+use crate::users::ServerCommand;
 
-use super::endscreen_handler::EndScreenHandler;
-
+/// Runs the phases of a game, the players are read from `game.utils` as bots can take over
+/// seats during the game
 pub(crate) struct SGame {
 	game: SharedTrivGame,
-	pub(crate) players: GamePlayerInfo,
-}
-
-pub(crate) mod emulation_config {
-	// todo read from .env or similar
-	pub(crate) const BASE_SELECTION: bool = false;
-	pub(crate) const AREA_SELECTION: bool = false;
-	pub(crate) const FILL_REMAINING: bool = false;
-	pub(crate) const BATTLE: bool = false;
 }
 
 impl SGame {
-	const PLAYER_COUNT: usize = 3;
-
-	pub(crate) fn new(game: SharedTrivGame, players: GamePlayerInfo) -> SGame {
-		trace!("Creating new SGame {:?} {:?}", game, players);
+	pub(crate) fn new(game: SharedTrivGame) -> SGame {
+		trace!("Creating new SGame {:?}", game);
 		SGame {
 			game: game.arc_clone(),
-			players,
 		}
 	}
 
-	pub(super) async fn handle_all(&mut self) {
+	pub(crate) async fn handle_all(&mut self) {
 		self.setup().await;
 		self.base_selection().await;
-		self.area_selection().await;
-		self.fill_remaining().await;
-		self.battle().await;
+		if !self.is_decided().await {
+			self.area_selection().await;
+		}
+		if !self.is_decided().await {
+			self.fill_remaining().await;
+		}
+		if !self.is_decided().await {
+			self.battle().await;
+		}
+		self.tiebreaker().await;
 		self.end_screen().await;
 	}
 
+	/// The game is over early if only one side is left or every human left the game
+	async fn is_decided(&self) -> bool {
+		let remaining = self.game.read().await.state.remaining_players().len();
+		remaining <= 1 || self.all_humans_left().await
+	}
+
+	async fn all_humans_left(&self) -> bool {
+		let game = self.game.read().await;
+		let humans = game
+			.players
+			.as_ref()
+			.map(|players| {
+				[&players.pd1, &players.pd2, &players.pd3]
+					.iter()
+					.filter(|data| !data.is_bot())
+					.count()
+			})
+			.unwrap_or(0);
+		humans > 0 && game.state.forfeited_players.len() >= humans
+	}
+
 	async fn setup(&self) {
-		self.game.write().await.state.game_state = GameState {
+		let mut game = self.game.write().await;
+		game.state.game_state = GameState {
 			state: 11,
 			round: 0,
 			phase: 0,
 		};
+		// every human gets a shield mission
+		let mut rng = game.rng.fork();
+		let map = game.state.areas_info.shared_map();
+		for player in game.utils.players() {
+			if game
+				.utils
+				.get_player(&player)
+				.is_some_and(|info| info.is_player())
+			{
+				game.state
+					.missions
+					.insert(player, PlayerMission::random(&map, &mut rng));
+			}
+		}
+		drop(game);
 		// this must be sent from here as the initial listen state is false
 		self.game.send_to_all_active().await;
 		self.game.wait_for_all_active().await;
@@ -67,7 +104,7 @@ impl SGame {
 
 	async fn base_selection(&self) {
 		trace!("base selection");
-		if emulation_config::BASE_SELECTION {
+		if config().emulation.base_selection {
 			SGameStateEmulator::base_selection(self.game.arc_clone()).await;
 		} else {
 			let base_handler = BaseHandler::new(self.game.arc_clone());
@@ -75,7 +112,11 @@ impl SGame {
 			self.game.write().await.state.active_player = None;
 			base_handler.announcement().await;
 			// pick a base for everyone
-			for (player, _) in self.players.0.clone() {
+			let players = self.game.read().await.utils.players();
+			for player in players {
+				if !self.game.read().await.state.is_in_play(&player) {
+					continue;
+				}
 				self.game.write().await.state.active_player = Some(player);
 				base_handler.start_selection().await;
 				base_handler.selection_response().await;
@@ -85,17 +126,20 @@ impl SGame {
 	}
 
 	async fn area_selection(&self) {
-		if emulation_config::AREA_SELECTION {
+		if config().emulation.area_selection {
 			SGameStateEmulator::area_selection(self.game.arc_clone()).await;
 		} else {
 			let area_handler = AreaConquerHandler::new(self.game.arc_clone());
-			let wo = Some(WarOrder::new_random_with_size(WarOrder::NORMAL_ROUND_COUNT));
-			self.game.write().await.state.war_order = wo.clone();
+			let round_count = self.game.read().await.rules.area_rounds;
+			self.new_war_order(round_count).await;
 			// setup area handler
 			area_handler.setup().await;
-			let mut mini_phase_counter = 0;
-			// todo change the round count based on the right answers
-			for _ in 1..=5 {
+			// spread until there are fewer free counties than players
+			for round in 0..round_count as usize {
+				if !self.game.read().await.state.spreading_continues() || self.is_decided().await {
+					break;
+				}
+				let players = self.rerank_war_order(round).await;
 				// announcement for all players
 				area_handler.announcement().await;
 				self.game.write().await.state.round_info = RoundInfo {
@@ -104,12 +148,7 @@ impl SGame {
 					attacked_player: None,
 				};
 				// select an area for everyone
-				for rel_player in wo
-					.clone()
-					.unwrap()
-					.get_next_players(mini_phase_counter, Self::PLAYER_COUNT)
-					.unwrap()
-				{
+				for rel_player in players {
 					// todo unify
 					self.game.write().await.state.active_player = Some(rel_player);
 					area_handler.ask_desired_area().await;
@@ -121,14 +160,12 @@ impl SGame {
 				game_writer.state.selection.clear();
 				game_writer.state.game_state.round += 1;
 				game_writer.state.round_info.mini_phase_num = 1;
-				drop(game_writer);
-				mini_phase_counter += Self::PLAYER_COUNT;
 			}
 		}
 	}
 
 	async fn fill_remaining(&mut self) {
-		if emulation_config::FILL_REMAINING {
+		if config().emulation.fill_remaining {
 			SGameStateEmulator::fill_remaining(self.game.arc_clone()).await;
 		} else {
 			let mut fill_remaining_handler = FillRemainingHandler::new(self.game.arc_clone());
@@ -136,7 +173,15 @@ impl SGame {
 			fill_remaining_handler.setup().await;
 			// todo improve constant write() calls
 			// while there are free areas fill them
-			while !self.game.read().await.state.available_areas.is_empty() {
+			while !self
+				.game
+				.read()
+				.await
+				.state
+				.areas_info
+				.free_counties()
+				.is_empty() && !self.is_decided().await
+			{
 				self.game.write().await.state.round_info.mini_phase_num += 1;
 				// announcement for players
 				fill_remaining_handler.announcement().await;
@@ -152,12 +197,13 @@ impl SGame {
 	}
 
 	async fn battle(&mut self) {
-		if emulation_config::BATTLE {
+		if config().emulation.battle {
 			warn!("add battle emu");
 		} else {
 			let mut battle_handler = BattleHandler::new(self.game.arc_clone());
-			let wo = WarOrder::standard_round();
-			self.game.write().await.state.war_order = Some(wo.clone());
+			let round_count = self.game.read().await.rules.war_rounds;
+			self.new_war_order(round_count).await;
+			let first = self.rerank_war_order(0).await;
 
 			// setup battle handler
 			self.game.write().await.state.active_player = None;
@@ -165,35 +211,22 @@ impl SGame {
 
 			self.game.write().await.state.round_info = RoundInfo {
 				mini_phase_num: 0,
-				active_player: *wo.get_next_players(0, 1).unwrap().first().unwrap(),
+				active_player: first.first().copied().unwrap_or(PlayerName::Nobody),
 				attacked_player: Some(PlayerName::Nobody),
 			};
 			// announcement for all players
 			battle_handler.announcement().await;
 
-			let mut mini_phase_counter = 0;
-			'war_loop: for _ in 0..=wo.order.len() / Self::PLAYER_COUNT {
+			'war_loop: for round in 0..round_count as usize {
 				// let everyone attack in order
-				for player in wo
-					.get_next_players(mini_phase_counter, Self::PLAYER_COUNT)
-					.unwrap()
-				{
+				for player in self.rerank_war_order(round).await {
 					// check if only one player is left
-					if self.game.read().await.state.eliminated_players.len()
-						>= Self::PLAYER_COUNT - 1
-					{
+					if self.is_decided().await {
 						info!("All players are eliminated, ending game");
 						break 'war_loop;
 					}
-					// skip eliminated players
-					if !self
-						.game
-						.read()
-						.await
-						.state
-						.eliminated_players
-						.contains(&player)
-					{
+					// skip players eliminated during this round
+					if self.game.read().await.state.is_in_play(&player) {
 						let mut game_write = self.game.write().await;
 						game_write.state.round_info.mini_phase_num += 1;
 						game_write.state.active_player = Some(player);
@@ -204,14 +237,50 @@ impl SGame {
 					}
 				}
 
-				self.game.write().await.state.game_state.round += 1;
-				self.game.write().await.state.round_info.mini_phase_num = 0;
+				let mut game_write = self.game.write().await;
+				game_write.state.game_state.round += 1;
+				game_write.state.round_info.mini_phase_num = 0;
+				game_write.state.war_round_over();
+			}
+		}
+	}
+
+	/// Starts a new war order for the phase ranked by the current points
+	async fn new_war_order(&self, round_count: u8) {
+		let mut game = self.game.write().await;
+		let players = game.state.remaining_players();
+		let mut rng = game.rng.fork();
+		let wo = WarOrder::ranked(&game.state.players_points, &players, round_count, &mut rng);
+		game.state.war_order = Some(wo);
+	}
 
-				mini_phase_counter += Self::PLAYER_COUNT;
+	/// Ranks the players of the round (and the rounds after it) by their current points, eliminated
+	/// players are left out, the first round is already ranked when the order is created
+	async fn rerank_war_order(&self, round: usize) -> Vec<PlayerName> {
+		let mut game = self.game.write().await;
+		let players = game.state.remaining_players();
+		let points = game.state.players_points.clone();
+		let mut rng = game.rng.fork();
+		match game.state.war_order.as_mut() {
+			Some(wo) => {
+				if round > 0 {
+					wo.rerank_from(round, &points, &players, &mut rng);
+				}
+				wo.round(round)
 			}
+			None => players,
 		}
 	}
 
+	async fn tiebreaker(&self) {
+		// nobody is left to watch it
+		if self.all_humans_left().await {
+			return;
+		}
+		let tiebreaker_handler = TiebreakerHandler::new(self.game.arc_clone());
+		tiebreaker_handler.handle_all().await;
+	}
+
 	async fn end_screen(&self) {
 		let end_screen_handler = EndScreenHandler::new(self.game.arc_clone());
 		end_screen_handler.handle_all().await;
@@ -229,7 +298,10 @@ struct SGameStateEmulator {}
 
 impl SGameStateEmulator {
 	pub(super) async fn base_selection(game: SharedTrivGame) {
-		game.write().await.state.available_areas = AvailableAreas::all_counties();
+		let mut write_game = game.write().await;
+		write_game.state.available_areas =
+			AvailableAreas::all_counties(write_game.state.areas_info.map());
+		drop(write_game);
 		let bh = BaseHandler::new(game.arc_clone());
 		bh.new_base_selected(1, PlayerName::Player1).await;
 		bh.new_base_selected(8, PlayerName::Player2).await;
@@ -237,14 +309,14 @@ impl SGameStateEmulator {
 	}
 
 	pub(super) async fn area_selection(game: SharedTrivGame) {
-		let mut rng = StdRng::from_entropy();
+		let mut rng = game.rng().await;
 		// this is useful for fill_remaining debugging
 		// let round_num = rng.gen_range(1..=5);
 		for _ in 1..=5 {
 			for player in PlayerName::all() {
 				let avail = &game.read().await.state.available_areas.clone();
 
-				let county = *avail.counties().iter().choose(&mut rng).unwrap();
+				let county = avail.choose(&mut rng).unwrap();
 				Area::area_occupied(game.arc_clone(), player, Option::from(county))
 					.await
 					.unwrap();
@@ -254,7 +326,7 @@ impl SGameStateEmulator {
 	}
 
 	pub(super) async fn fill_remaining(game: SharedTrivGame) {
-		let mut rng = StdRng::from_entropy();
+		let mut rng = game.rng().await;
 		loop {
 			let avail = game.read().await.state.available_areas.clone();
 
@@ -262,7 +334,7 @@ impl SGameStateEmulator {
 				break;
 			}
 
-			let area = *avail.counties().iter().choose(&mut rng).unwrap();
+			let area = avail.choose(&mut rng).unwrap();
 			Area::area_occupied(
 				game.arc_clone(),
 				PlayerName::all().choose(&mut rng).unwrap(),
@@ -301,6 +373,22 @@ impl GamePlayerInfo {
 		tokio_stream::iter(&self.0)
 	}
 
+	/// Returns the players ordered by their seat
+	pub(crate) fn players(&self) -> Vec<PlayerName> {
+		let mut players: Vec<PlayerName> = self.0.keys().copied().collect();
+		players.sort_by_key(|player| *player as u8);
+		players
+	}
+
+	/// The seats of the connected players e.g. `13`, robots are always connected
+	pub(crate) fn connected_players(&self) -> String {
+		self.players()
+			.iter()
+			.filter(|player| !self.0[player].is_disconnected())
+			.map(|player| (*player as u8).to_string())
+			.collect()
+	}
+
 	#[allow(dead_code)]
 	pub(crate) fn players_stream(&self) -> impl Stream<Item = &PlayerName> + '_ {
 		tokio_stream::iter(self.0.keys())
@@ -348,23 +436,83 @@ impl From<HashMap<PlayerName, SGamePlayerInfo>> for GamePlayerInfo {
 #[derive(Clone, Debug)]
 pub(crate) struct SGamePlayerInfo {
 	active: bool,
+	disconnected: bool,
+	missed_ready: u8,
 	cmd: Option<Cmd>,
+	/// The last command sent to the player which is still waiting for an answer
+	pending_cmd: Option<Cmd>,
 	channels: Option<GamePlayerChannels>,
+	/// Plays for robots and for the player while they are disconnected
+	bot: BotStrategy,
 }
 
 impl SGamePlayerInfo {
+	/// Ready timeouts in a row after which the player is considered disconnected
+	const MAX_MISSED_READY: u8 = 3;
+
 	pub(crate) fn new(is_active: bool) -> SGamePlayerInfo {
 		SGamePlayerInfo {
 			active: is_active,
+			disconnected: false,
+			missed_ready: 0,
 			cmd: None,
+			pending_cmd: None,
 			channels: None,
+			bot: BotStrategy::default(),
+		}
+	}
+
+	pub(crate) fn new_bot(bot: BotStrategy) -> SGamePlayerInfo {
+		SGamePlayerInfo {
+			bot,
+			..SGamePlayerInfo::new(false)
 		}
 	}
 
+	pub(crate) fn bot(&self) -> BotStrategy {
+		self.bot
+	}
+
 	pub(crate) fn is_player(&self) -> bool {
 		self.active
 	}
 
+	pub(crate) fn is_disconnected(&self) -> bool {
+		self.disconnected
+	}
+
+	/// From now on a bot plays instead of the player
+	pub(crate) fn disconnect(&mut self) {
+		self.active = false;
+		self.disconnected = true;
+		self.cmd = None;
+		// a receiver might still wait on the removed channels
+		if let Some(channels) = self.channels.take() {
+			let _ = channels
+				.command_channel
+				.try_send_message(ServerCommand::ChannelsReplaced);
+		}
+	}
+
+	/// The player takes back their seat from the bot with new channels
+	pub(crate) fn rejoin(&mut self, channels: GamePlayerChannels) {
+		self.active = true;
+		self.disconnected = false;
+		self.missed_ready = 0;
+		self.cmd = self.pending_cmd.clone();
+		self.channels = Some(channels);
+	}
+
+	pub(crate) fn ready_received(&mut self) {
+		self.missed_ready = 0;
+	}
+
+	/// Returns true if the player missed too many ready commands in a row
+	pub(crate) fn ready_missed(&mut self) -> bool {
+		self.missed_ready += 1;
+		self.missed_ready >= Self::MAX_MISSED_READY
+	}
+
 	#[allow(dead_code)]
 	pub(crate) fn set_active(&mut self, active: bool) {
 		self.active = active;
@@ -377,6 +525,17 @@ impl SGamePlayerInfo {
 		&self.cmd
 	}
 
+	/// The command was sent to the player, it's kept until it's answered
+	pub(crate) fn cmd_sent(&mut self) {
+		if let Some(cmd) = self.cmd.take() {
+			self.pending_cmd = Some(cmd);
+		}
+	}
+
+	pub(crate) fn cmd_answered(&mut self) {
+		self.pending_cmd = None;
+	}
+
 	pub(crate) fn set_channels(&mut self, channels: Option<GamePlayerChannels>) {
 		self.channels = channels;
 	}
@@ -385,3 +544,59 @@ impl SGamePlayerInfo {
 		&self.channels
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn disconnect_after_missed_readies() {
+		let mut info = SGamePlayerInfo::new(true);
+		assert!(!info.ready_missed());
+		info.ready_received();
+		assert!(!info.ready_missed());
+		assert!(!info.ready_missed());
+		assert!(info.ready_missed());
+	}
+
+	#[test]
+	fn rejoin_restores_pending_cmd() {
+		let mut info = SGamePlayerInfo::new(true);
+		info.set_cmd(Some(Cmd::tip_command(Cmd::tip_timeout())));
+		info.cmd_sent();
+		info.disconnect();
+		assert!(info.get_cmd().is_none());
+
+		let channels = GamePlayerChannels::new(Default::default(), Default::default());
+		info.rejoin(channels);
+		assert!(info.is_player());
+		assert_eq!(info.get_cmd().as_ref().unwrap().command, "TIP");
+		info.cmd_sent();
+		info.cmd_answered();
+		info.rejoin(GamePlayerChannels::new(
+			Default::default(),
+			Default::default(),
+		));
+		assert!(info.get_cmd().is_none());
+	}
+
+	#[test]
+	fn connected_players() {
+		let mut players = GamePlayerInfo::new();
+		players.add(PlayerName::Player1, SGamePlayerInfo::new(true));
+		players.add(PlayerName::Player2, SGamePlayerInfo::new(true));
+		players.add(PlayerName::Player3, SGamePlayerInfo::new(false));
+		assert_eq!(players.connected_players(), "123");
+		players
+			.get_player_mut(&PlayerName::Player2)
+			.unwrap()
+			.disconnect();
+		assert!(
+			!players
+				.get_player(&PlayerName::Player2)
+				.unwrap()
+				.is_player()
+		);
+		assert_eq!(players.connected_players(), "13");
+	}
+}
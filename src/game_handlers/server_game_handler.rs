@@ -1,16 +1,22 @@
-use sqlx::PgPool;
+use std::sync::Arc;
+
 use tokio_stream::StreamExt;
-use tracing::info;
+use tracing::{error, info};
 
 use super::s_game::GamePlayerInfo;
 use crate::app::{
 	GamePlayerChannels, GroupedCommChannels, ListenPlayerChannel, ServerCommandChannel,
 };
+use crate::bot::strategy::BotDifficulty;
 use crate::emulator::Emulator;
 use crate::game_handlers::s_game::{SGame, SGamePlayerInfo};
+use crate::storage::SharedStorage;
 use crate::triviador::game::{SharedTrivGame, TriviadorGame};
 use crate::triviador::game_player_data::{GamePlayerData, PlayerName};
+use crate::triviador::game_rng::GameRng;
+use crate::triviador::map::GameMap;
 use crate::triviador::player_info::PlayerInfo;
+use crate::triviador::question_provider::StoredQuestions;
 use crate::village::start::friendly_game::OpponentType;
 
 pub(crate) struct ServerGameHandler {}
@@ -22,36 +28,53 @@ impl ServerGameHandler {
 		player_channel: ListenPlayerChannel,
 		command_channel: ServerCommandChannel,
 		game_id: u32,
-		db: PgPool,
+		storage: SharedStorage,
 	) {
+		let game_rng = GameRng::from_config();
+		let mut rng = game_rng.fork();
 		let players = PlayerInfo {
 			p1_name: "xrtxn".to_string(),
 			p2_name: "null".to_string(),
 			p3_name: "null".to_string(),
 			pd1: GamePlayerData::emulate(),
-			pd2: GamePlayerData::new_bot(),
-			pd3: GamePlayerData::new_bot(),
+			pd2: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
+			pd3: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
 			you: "1,2,3".to_string(),
 			game_id,
 			room: "1".to_string(),
 			rules: "0,0".to_string(),
 		};
 
-		let game = SharedTrivGame::new(TriviadorGame::new_game(players.clone(), db));
+		let game = SharedTrivGame::new(TriviadorGame::new_game(
+			players.clone(),
+			Some(storage.clone()),
+			Arc::new(StoredQuestions::new(storage)),
+			GameMap::hungary(),
+			game_rng,
+		));
 		// todo check
 		let mut server_game_players = GamePlayerInfo::new();
 		if players.pd1.is_bot() {
-			server_game_players.add(PlayerName::Player1, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player1,
+				SGamePlayerInfo::new_bot(players.pd1.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player1, SGamePlayerInfo::new(true));
 		}
 		if players.pd2.is_bot() {
-			server_game_players.add(PlayerName::Player2, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player2,
+				SGamePlayerInfo::new_bot(players.pd2.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player2, SGamePlayerInfo::new(true));
 		}
 		if players.pd3.is_bot() {
-			server_game_players.add(PlayerName::Player3, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player3,
+				SGamePlayerInfo::new_bot(players.pd3.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player3, SGamePlayerInfo::new(true));
 		}
@@ -75,7 +98,7 @@ impl ServerGameHandler {
 		}
 
 		// initial setup
-		let mut server_game = SGame::new(game.arc_clone(), server_game_players.clone());
+		let mut server_game = SGame::new(game.arc_clone());
 
 		server_game.handle_all().await;
 		info!("Game ended");
@@ -85,15 +108,17 @@ impl ServerGameHandler {
 		command_channel.clear_rx();
 	}
 
-	pub async fn new_friendly_with_players(
-		grouped: GroupedCommChannels,
-		players: PlayerInfo,
-		db: PgPool,
-	) {
-		let game = SharedTrivGame::new(TriviadorGame::new_game(players.clone(), db));
+	pub async fn new_friendly_with_players(grouped: GroupedCommChannels, game: SharedTrivGame) {
+		let Some(players) = game.read().await.players.clone() else {
+			error!("Game started without players");
+			return;
+		};
 		let mut server_game_players = GamePlayerInfo::new();
 		if players.pd1.is_bot() {
-			server_game_players.add(PlayerName::Player1, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player1,
+				SGamePlayerInfo::new_bot(players.pd1.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player1, SGamePlayerInfo::new(true));
 			server_game_players
@@ -108,7 +133,10 @@ impl ServerGameHandler {
 				));
 		}
 		if players.pd2.is_bot() {
-			server_game_players.add(PlayerName::Player2, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player2,
+				SGamePlayerInfo::new_bot(players.pd2.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player2, SGamePlayerInfo::new(true));
 			server_game_players
@@ -123,7 +151,10 @@ impl ServerGameHandler {
 				));
 		}
 		if players.pd3.is_bot() {
-			server_game_players.add(PlayerName::Player3, SGamePlayerInfo::new(false));
+			server_game_players.add(
+				PlayerName::Player3,
+				SGamePlayerInfo::new_bot(players.pd3.bot_strategy()),
+			);
 		} else {
 			server_game_players.add(PlayerName::Player3, SGamePlayerInfo::new(true));
 			server_game_players
@@ -145,7 +176,7 @@ impl ServerGameHandler {
 		}
 
 		// initial setup
-		let mut server_game = SGame::new(game.arc_clone(), server_game_players.clone());
+		let mut server_game = SGame::new(game.arc_clone());
 
 		server_game.handle_all().await;
 		info!("Game ended");
@@ -0,0 +1,127 @@
+use std::cmp::Reverse;
+
+use tracing::{info, trace};
+
+use crate::game_handlers::question_handler::{TipHandler, TipHandlerType};
+use crate::triviador::game::SharedTrivGame;
+use crate::triviador::game_player_data::PlayerName;
+use crate::triviador::game_state::GameState;
+use crate::triviador::player_points::PlayerPoints;
+
+// WAR OVER
+// Setup
+// 5,1,0
+// Announcement,
+// 5,1,1
+// TipQuestion,
+// 5,1,3
+// TipResults,
+
+pub(crate) struct TiebreakerHandler {
+	game: SharedTrivGame,
+}
+
+impl TiebreakerHandler {
+	pub(crate) fn new(game: SharedTrivGame) -> TiebreakerHandler {
+		TiebreakerHandler { game }
+	}
+
+	/// Runs a tip question between the players level on the most points, its winner is placed
+	/// first. Ties below the first place are left as they are
+	pub(super) async fn handle_all(&self) {
+		let game = self.game.read().await;
+		let left: Vec<PlayerName> = game
+			.state
+			.forfeited_players
+			.iter()
+			.chain(&game.state.eliminated_players)
+			.copied()
+			.collect();
+		let group = tie_for_first(&game.state.players_points, &left);
+		drop(game);
+		let Some(group) = group else {
+			return;
+		};
+		info!("Tiebreaker between {:?}", group);
+		self.setup().await;
+		self.announcement().await;
+		// bots take over the seats of players who disconnected since the war ended
+		let players = self.game.read().await.utils.retain_players(group.clone());
+		let mut th =
+			TipHandler::new(self.game.arc_clone(), TipHandlerType::Tiebreaker, players).await;
+		let winner = th.handle_all().await;
+		if group.contains(&winner) {
+			self.game.write().await.state.tiebreak_ranking.push(winner);
+		}
+	}
+
+	async fn setup(&self) {
+		let mut game = self.game.write().await;
+		game.state.game_state = GameState {
+			state: 5,
+			round: 1,
+			phase: 0,
+		};
+		game.state.active_player = None;
+	}
+
+	async fn announcement(&self) {
+		self.game.write().await.state.game_state.phase = 0;
+		self.game.send_to_all_active().await;
+		trace!("Tiebreaker announcement waiting");
+		self.game.wait_for_all_active().await;
+		trace!("Tiebreaker announcement game ready");
+	}
+}
+
+/// The players level on the most points if there are more of them, players who forfeited or were
+/// eliminated are placed last anyway
+fn tie_for_first(points: &PlayerPoints, left: &[PlayerName]) -> Option<Vec<PlayerName>> {
+	let mut players: Vec<PlayerName> = PlayerName::all()
+		.filter(|player| !left.contains(player))
+		.collect();
+	players.sort_by_key(|player| Reverse(points.get_player_points(player)));
+	players
+		.chunk_by(|a, b| points.get_player_points(a) == points.get_player_points(b))
+		.next()
+		.filter(|group| group.len() > 1)
+		.map(|group| group.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_tied_players() {
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player1, 1200);
+		points.set_player_points(&PlayerName::Player2, 1000);
+		points.set_player_points(&PlayerName::Player3, 1200);
+		assert_eq!(
+			tie_for_first(&points, &[]),
+			Some(vec![PlayerName::Player1, PlayerName::Player3])
+		);
+		assert_eq!(tie_for_first(&points, &[PlayerName::Player3]), None);
+
+		points.set_player_points(&PlayerName::Player2, 1200);
+		assert_eq!(
+			tie_for_first(&points, &[PlayerName::Player2]),
+			Some(vec![PlayerName::Player1, PlayerName::Player3])
+		);
+	}
+
+	#[test]
+	fn ties_at_the_bottom_are_not_played() {
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player1, 1000);
+		points.set_player_points(&PlayerName::Player2, 1400);
+		points.set_player_points(&PlayerName::Player3, 1000);
+		assert_eq!(tie_for_first(&points, &[]), None);
+		// the leader left, the players level on points play for the first place
+		assert_eq!(
+			tie_for_first(&points, &[PlayerName::Player2]),
+			Some(vec![PlayerName::Player1, PlayerName::Player3])
+		);
+	}
+}
@@ -4,15 +4,20 @@ use tracing_subscriber::util::SubscriberInitExt;
 
 use crate::app::{App, AppError};
 
+mod admin;
 mod app;
+mod bot;
 mod cdn;
 mod channels;
+mod config;
 mod emulator;
 mod game_handlers;
 mod login_screen;
 mod menu;
 mod mobile;
 mod router;
+mod simulation;
+mod storage;
 mod triviador;
 mod users;
 mod utils;
@@ -20,12 +25,22 @@ mod village;
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
+	let simulated_games = simulation::games_from_args(std::env::args());
+	// the logs of simulated games would hide the report
+	let default_filter = match simulated_games {
+		Some(_) => "warn",
+		None => "sqlx=warn,tower_http=debug,honfoglalo_classic_server=trace,fred=info",
+	};
 	tracing_subscriber::registry()
-		.with(EnvFilter::new(std::env::var("RUST_LOG").unwrap_or_else(
-			|_| "sqlx=warn,tower_http=debug,honfoglalo_classic_server=trace,fred=info".into(),
-		)))
+		.with(EnvFilter::new(
+			std::env::var("RUST_LOG").unwrap_or_else(|_| default_filter.into()),
+		))
 		.with(tracing_subscriber::fmt::layer())
 		.init();
 
+	config::init()?;
+	if let Some(games) = simulated_games {
+		return Ok(simulation::run(games)?);
+	}
 	App::new().await?.serve().await
 }
@@ -1,12 +1,13 @@
+use axum::extract::Query;
 use axum::{Extension, Json};
-use sqlx::PgPool;
 use tracing::{error, trace, warn};
 
 use crate::app::{
-	AppError, FriendlyRooms, GamePlayerChannels, ListenPlayerChannel, ServerCommandChannel,
-	SharedPlayerState,
+	ActiveGames, AppError, FriendlyRooms, GamePlayerChannels, GameRegistry, ListenPlayerChannel,
+	RunningGame, ServerCommandChannel, SharedPlayerState,
 };
-use crate::cdn::countries::CountriesResponse;
+use crate::bot::strategy::BotDifficulty;
+use crate::cdn::countries::{CountriesRequest, CountriesResponse};
 use crate::channels::BodyChannelType;
 use crate::channels::command::request::{CommandRoot, CommandType};
 use crate::channels::command::response::CommandResponse;
@@ -14,31 +15,39 @@ use crate::channels::heartbeat::request::response::HeartBeatResponse;
 use crate::channels::listen::request::ListenRoot;
 use crate::channels::listen::response::ListenResponseHeader;
 use crate::emulator::Emulator;
+use crate::game_handlers::replay_handler;
 use crate::game_handlers::server_game_handler::ServerGameHandler;
 use crate::menu::friend_list::external_data::ExternalFriendsRoot;
 use crate::menu::friend_list::friends::FriendResponse;
 use crate::menu::help::info_help::HelpResponse;
-use crate::mobile::request::Mobile;
+use crate::mobile::request::{LoginError, Mobile};
 use crate::mobile::response::{LoginResponse, MobileResponse, PingResponse};
+use crate::storage::{self, SharedStorage};
+use crate::triviador::game::{SharedTrivGame, TriviadorGame};
 use crate::triviador::game_player_data::GamePlayerData;
+use crate::triviador::game_rng::GameRng;
+use crate::triviador::map::MapRegistry;
 use crate::triviador::player_info::PlayerInfo;
+use crate::triviador::question_provider::SharedQuestionProvider;
 use crate::users::ServerCommand;
 use crate::utils::{modified_xml_response, remove_root_tag};
 use crate::village::castle::badges::CastleResponse;
 use crate::village::setup::VillageSetupRoot;
-use crate::village::start::friendly_game::ActiveSepRoom;
 use crate::village::start::friendly_game::OpponentType;
+use crate::village::start::friendly_game::{ActiveSepRoom, FriendlyListRooms};
 use crate::village::waithall::{GameMenuWaithall, Waithall};
 
-const QUICK_BATTLE_EMU: bool = false;
-
 pub async fn help() -> Json<HelpResponse> {
 	// todo find out how to use this
 	Json(HelpResponse::emulate())
 }
 
-pub async fn countries() -> Json<CountriesResponse> {
-	Json(CountriesResponse::emulate())
+pub async fn countries(
+	maps: Extension<MapRegistry>,
+	Query(request): Query<CountriesRequest>,
+) -> Json<CountriesResponse> {
+	let map = maps.get_or_default(request.map.as_deref());
+	Json(CountriesResponse::from_map(&map))
 }
 pub async fn friends() -> Json<FriendResponse> {
 	Json(FriendResponse::emulate())
@@ -48,34 +57,43 @@ pub async fn _extdata() -> Json<FriendResponse> {
 	Json(FriendResponse::emulate())
 }
 
-pub async fn mobil(Json(payload): Json<Mobile>) -> Json<MobileResponse> {
+pub async fn mobil(
+	storage: Extension<SharedStorage>,
+	Json(payload): Json<Mobile>,
+) -> Json<MobileResponse> {
 	match payload {
 		Mobile::Ping(_) => Json(MobileResponse::Ping(PingResponse::pong())),
 		Mobile::MobileLogin(login_req) => {
 			let mut login = LoginResponse::nopass_login(login_req.username.clone());
-			if login_req.username == "felso" {
-				login.data.userid = "1".to_string();
-			} else {
-				login.data.userid = "6".to_string();
-			};
+			match storage::login(&storage, &login_req.username).await {
+				Ok(id) => login.data.userid = id.to_string(),
+				Err(e) => {
+					error!("Failed to log in {}: {}", login_req.username, e);
+					login.data.error = Some(LoginError {
+						message: "Login failed".to_string(),
+					});
+				}
+			}
 			Json(MobileResponse::Login(login))
 		}
 	}
 }
 
 #[axum::debug_handler]
+#[allow(clippy::too_many_arguments)]
 pub async fn game(
-	db: Extension<PgPool>,
+	storage: Extension<SharedStorage>,
 	xml_header: Extension<BodyChannelType>,
 	session: Extension<SharedPlayerState>,
 	friendly_rooms: Extension<FriendlyRooms>,
+	active_games: Extension<ActiveGames>,
+	games: Extension<GameRegistry>,
+	maps: Extension<MapRegistry>,
+	questions: Extension<SharedQuestionProvider>,
 	player_listen_channel: Extension<ListenPlayerChannel>,
 	server_command_channel: Extension<ServerCommandChannel>,
 	body: String,
 ) -> Result<String, AppError> {
-	// todo fetch this from db
-	const GAME_ID: u32 = 1;
-
 	let body = format!("<ROOT>{}</ROOT>", body);
 	match xml_header.0 {
 		BodyChannelType::Command(comm) => {
@@ -102,6 +120,14 @@ pub async fn game(
 						}
 					};
 					player_listen_channel.send_message(msg).await.unwrap();
+					// the friendly rooms are listed in the game menu
+					if chw.waithall == Waithall::Game {
+						let rooms = FriendlyListRooms::new(&friendly_rooms).await;
+						player_listen_channel
+							.send_message(quick_xml::se::to_string(&rooms)?)
+							.await
+							.unwrap();
+					}
 					Ok(modified_xml_response(&CommandResponse::ok(
 						comm.client_id,
 						comm.mn,
@@ -119,6 +145,7 @@ pub async fn game(
 					)))
 				}
 				CommandType::ExitCurrentRoom(_) => {
+					stop_spectating(&session, &friendly_rooms).await;
 					// todo remove player from friendly game menu
 					if session.read_lock().await.current_waithall == Waithall::Game {
 						if session.read_lock().await.friendly_game_code.is_some() {
@@ -145,6 +172,14 @@ pub async fn game(
 					))?)
 				}
 				CommandType::CloseGame => {
+					stop_spectating(&session, &friendly_rooms).await;
+					// leaving a game in progress counts as a forfeit
+					let player_id = session.read_lock().await.player_id;
+					if let Some(game) = active_games.remove_async(&player_id).await
+						&& let Err(e) = game.forfeit_player(player_id).await
+					{
+						warn!("Unable to forfeit game: {}", e);
+					}
 					// send back to the menu
 					let msg = quick_xml::se::to_string(&GameMenuWaithall::emulate())?;
 					player_listen_channel.send_message(msg).await.unwrap();
@@ -183,12 +218,26 @@ pub async fn game(
 							OpponentType::Player(session.read_lock().await.player_id),
 						)
 						.await;
-					room.add_opponent(request_room.opp1, request_room.name1)
-						.unwrap();
-					room.add_opponent(request_room.opp2, request_room.name2)
-						.unwrap();
+					let difficulty = request_room.bot_difficulty();
+					room.add_opponent(
+						request_room.opp1.with_bot_difficulty(difficulty),
+						request_room.name1,
+					)
+					.unwrap();
+					room.add_opponent(
+						request_room.opp2.with_bot_difficulty(difficulty),
+						request_room.name2,
+					)
+					.unwrap();
 
 					room.code = Some(room_number);
+					room.map = match request_room.map {
+						Some(name) if maps.get(&name).is_none() => {
+							warn!("Unknown map {}, using the default map", name);
+							None
+						}
+						map => map,
+					};
 					session.write_lock().await.friendly_game_code = Some(room_number);
 
 					room.player1_ready = true;
@@ -258,33 +307,48 @@ pub async fn game(
 					))?)
 				}
 				CommandType::StartTriviador(_) => {
-					let room = friendly_rooms
-						.0
-						.get_async(&session.read_lock().await.friendly_game_code.unwrap())
-						.await
-						.unwrap()
-						.clone();
+					let code = session.read_lock().await.friendly_game_code.unwrap();
+					let room = friendly_rooms.0.get_async(&code).await.unwrap().clone();
+					let game_id = match storage.new_game_id().await {
+						Ok(game_id) => game_id,
+						Err(e) => {
+							error!("Failed to allocate a game id: {}", e);
+							return Ok(modified_xml_response(&CommandResponse::error())?);
+						}
+					};
 
-					let pd1 = GamePlayerData::new_player(room.player1.get_id());
+					let game_rng = GameRng::from_config();
+					let mut rng = game_rng.fork();
+					let pd1 =
+						GamePlayerData::load_player(&storage, room.player1.get_id(), &mut rng)
+							.await;
 					let pd2 = match &room.player2 {
 						Some(opponent) => match opponent {
-							OpponentType::Player(id) => GamePlayerData::new_player(*id),
-							OpponentType::Robot => GamePlayerData::new_bot(),
+							OpponentType::Player(id) => {
+								GamePlayerData::load_player(&storage, *id, &mut rng).await
+							}
+							OpponentType::Robot(difficulty) => {
+								GamePlayerData::new_bot(*difficulty, &mut rng)
+							}
 							_ => {
 								unimplemented!()
 							}
 						},
-						None => GamePlayerData::new_bot(),
+						None => GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
 					};
 					let pd3 = match &room.player3 {
 						Some(opponent) => match opponent {
-							OpponentType::Player(id) => GamePlayerData::new_player(*id),
-							OpponentType::Robot => GamePlayerData::new_bot(),
+							OpponentType::Player(id) => {
+								GamePlayerData::load_player(&storage, *id, &mut rng).await
+							}
+							OpponentType::Robot(difficulty) => {
+								GamePlayerData::new_bot(*difficulty, &mut rng)
+							}
 							_ => {
 								unimplemented!()
 							}
 						},
-						None => GamePlayerData::new_bot(),
+						None => GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
 					};
 
 					let info = PlayerInfo {
@@ -301,18 +365,33 @@ pub async fn game(
 						pd2,
 						pd3,
 						you: "1,2,3".to_string(),
-						game_id: GAME_ID,
+						game_id,
 						room: "1".to_string(),
 						rules: "0,0".to_string(),
 					};
-					tokio::spawn(async move {
-						ServerGameHandler::new_friendly_with_players(
-							room.get_channels().await,
-							info,
-							db.0,
+					let user_ids = info.user_ids();
+					let map = maps.get_or_default(room.map.as_deref());
+					let game = SharedTrivGame::new(TriviadorGame::new_game(
+						info,
+						Some(storage.0.clone()),
+						questions.0,
+						map,
+						game_rng,
+					));
+					// the game is kept in the room so others can watch it
+					if let Some(mut active_room) = friendly_rooms.get_async(&code).await {
+						active_room.get_mut().game = Some(game.arc_clone());
+					}
+					let channels = room.get_channels().await;
+					games
+						.start(
+							game_id,
+							RunningGame::new(game.arc_clone(), user_ids).with_room(code),
+							active_games.0,
+							friendly_rooms.0.clone(),
+							ServerGameHandler::new_friendly_with_players(channels, game),
 						)
 						.await;
-					});
 					Ok(modified_xml_response(&CommandResponse::ok(
 						comm.client_id,
 						comm.mn,
@@ -358,6 +437,74 @@ pub async fn game(
 						comm.mn,
 					))?)
 				}
+				CommandType::Spectate(room) => {
+					let Some(code) = room.code else {
+						warn!("Provided code must be some");
+						return Ok(modified_xml_response(&CommandResponse::error())?);
+					};
+					let game = friendly_rooms
+						.get_async(&code)
+						.await
+						.and_then(|active_room| active_room.get().game.clone());
+					let Some(game) = game else {
+						warn!("No game is running in friendly room {}", code);
+						return Ok(modified_xml_response(&CommandResponse::error())?);
+					};
+					if game.read().await.state.game_state.state == 15 {
+						warn!("The game in friendly room {} is over", code);
+						return Ok(modified_xml_response(&CommandResponse::error())?);
+					}
+
+					stop_spectating(&session, &friendly_rooms).await;
+					if let Some(mut active_room) = friendly_rooms.get_async(&code).await {
+						active_room.get_mut().spectators += 1;
+					}
+					let player_id = {
+						let mut state = session.write_lock().await;
+						state.spectating = Some(code);
+						state.current_waithall = Waithall::Game;
+						state.player_id
+					};
+					game.add_spectator(player_id, player_listen_channel.0.clone())
+						.await;
+
+					Ok(modified_xml_response(&CommandResponse::ok(
+						comm.client_id,
+						comm.mn,
+					))?)
+				}
+				CommandType::Replay(req) => {
+					let user_id = session.read_lock().await.player_id;
+					let log = match replay_handler::load(&storage, req.game_id, user_id).await {
+						Ok(log) => log,
+						Err(e) => {
+							warn!("No replay for game {}: {}", req.game_id, e);
+							return Ok(modified_xml_response(&CommandResponse::error())?);
+						}
+					};
+					stop_spectating(&session, &friendly_rooms).await;
+					session.write_lock().await.current_waithall = Waithall::Game;
+					tokio::spawn(replay_handler::play(
+						log,
+						player_listen_channel.0.clone(),
+						req.speed.unwrap_or(1),
+					));
+
+					Ok(modified_xml_response(&CommandResponse::ok(
+						comm.client_id,
+						comm.mn,
+					))?)
+				}
+				CommandType::UseHelp(help) => {
+					server_command_channel
+						.send_message(ServerCommand::UseHelp(help.help))
+						.await?;
+
+					Ok(modified_xml_response(&CommandResponse::ok(
+						comm.client_id,
+						comm.mn,
+					))?)
+				}
 			}
 		}
 		BodyChannelType::Listen(lis) => {
@@ -376,7 +523,10 @@ pub async fn game(
 
 			session.write_lock().await.is_listen_ready = ser.listen.is_ready;
 
-			let msg = player_listen_channel.recv_message().await.unwrap();
+			let Ok(msg) = player_listen_channel.recv_message().await else {
+				warn!("The listen channel of {} is closed", lis.client_id);
+				return Ok(modified_xml_response(&CommandResponse::error())?);
+			};
 			Ok(format!(
 				"{}\n{}",
 				quick_xml::se::to_string(&ListenResponseHeader {
@@ -394,6 +544,25 @@ pub async fn game(
 	}
 }
 
+/// Stops watching the game the player spectates
+pub(crate) async fn stop_spectating(session: &SharedPlayerState, friendly_rooms: &FriendlyRooms) {
+	let mut state = session.write_lock().await;
+	let Some(code) = state.spectating.take() else {
+		return;
+	};
+	let game = match friendly_rooms.get_async(&code).await {
+		Some(mut room) => {
+			let room = room.get_mut();
+			room.spectators = room.spectators.saturating_sub(1);
+			room.game.clone()
+		}
+		None => None,
+	};
+	if let Some(game) = game {
+		game.remove_spectator(state.player_id).await;
+	}
+}
+
 pub async fn client_castle() -> Json<CastleResponse> {
 	Json(CastleResponse::emulate())
 }
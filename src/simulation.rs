@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::Instant;
+
+use crate::bot::strategy::BotDifficulty;
+use crate::game_handlers::endscreen_handler::ranking;
+use crate::game_handlers::s_game::{GamePlayerInfo, SGame, SGamePlayerInfo};
+use crate::triviador::game::{SharedTrivGame, TriviadorGame};
+use crate::triviador::game_player_data::{GamePlayerData, PlayerName};
+use crate::triviador::game_rng::GameRng;
+use crate::triviador::map::GameMap;
+use crate::triviador::player_info::PlayerInfo;
+use crate::triviador::question_provider::InMemoryQuestions;
+use crate::triviador::rules::GameRules;
+
+/// The bots of the seats in every simulated game
+const SEATS: [(PlayerName, BotDifficulty); 3] = [
+	(PlayerName::Player1, BotDifficulty::Easy),
+	(PlayerName::Player2, BotDifficulty::Normal),
+	(PlayerName::Player3, BotDifficulty::Hard),
+];
+
+/// Generated questions of a simulated game
+const QUESTIONS: usize = 100;
+
+/// Rules the simulated games are played with
+pub(crate) struct RuleSet {
+	name: &'static str,
+	rules: GameRules,
+}
+
+fn rule_sets() -> Vec<RuleSet> {
+	vec![
+		RuleSet {
+			name: "default",
+			rules: GameRules::default(),
+		},
+		RuleSet {
+			name: "short war",
+			rules: GameRules {
+				war_rounds: 3,
+				..GameRules::default()
+			},
+		},
+		RuleSet {
+			name: "short spreading",
+			rules: GameRules {
+				area_rounds: 2,
+				..GameRules::default()
+			},
+		},
+	]
+}
+
+/// The outcome of a single simulated game
+#[derive(Debug)]
+pub(crate) struct GameResult {
+	winner: PlayerName,
+	points: HashMap<PlayerName, i16>,
+	/// Time the game would have taken with the delays of the bots
+	length: Duration,
+}
+
+/// Results of every game played with a rule set
+#[derive(Default)]
+struct Report {
+	games: u32,
+	wins: HashMap<PlayerName, u32>,
+	points: HashMap<PlayerName, i64>,
+	length: Duration,
+}
+
+impl Report {
+	fn add(&mut self, result: GameResult) {
+		self.games += 1;
+		*self.wins.entry(result.winner).or_default() += 1;
+		for (player, points) in result.points {
+			*self.points.entry(player).or_default() += i64::from(points);
+		}
+		self.length += result.length;
+	}
+
+	fn print(&self, name: &str) {
+		let games = self.games.max(1);
+		let length = (self.length / games).as_secs();
+		println!("{} ({} games)", name, self.games);
+		println!("  average length: {}m {}s", length / 60, length % 60);
+		for (player, difficulty) in SEATS {
+			let wins = self.wins.get(&player).copied().unwrap_or(0);
+			let points = self.points.get(&player).copied().unwrap_or(0);
+			println!(
+				"  player {} ({:?}): {:.1}% won, {} points on average",
+				player,
+				difficulty,
+				f64::from(wins) * 100.0 / f64::from(games),
+				points / i64::from(games)
+			);
+		}
+	}
+}
+
+/// The number of games if the server was started with `simulate [games]`
+pub(crate) fn games_from_args(mut args: impl Iterator<Item = String>) -> Option<u32> {
+	if args.nth(1)? != "simulate" {
+		return None;
+	}
+	Some(
+		args.next()
+			.and_then(|games| games.parse().ok())
+			.unwrap_or(100),
+	)
+}
+
+/// Plays the games of every rule set with three bots and prints the results. The games run on
+/// their own runtime with paused time, so the delays of the bots are skipped
+pub(crate) fn run(games: u32) -> anyhow::Result<()> {
+	std::thread::spawn(move || -> anyhow::Result<()> {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_time()
+			.start_paused(true)
+			.build()?;
+		runtime.block_on(async {
+			for rule_set in rule_sets() {
+				let mut report = Report::default();
+				for seed in 0..u64::from(games) {
+					report.add(simulate_game(&rule_set.rules, seed).await);
+				}
+				report.print(rule_set.name);
+			}
+		});
+		Ok(())
+	})
+	.join()
+	.map_err(|_| anyhow::anyhow!("Simulation panicked"))?
+	.context("Simulation failed")
+}
+
+/// Plays a whole game with bots in every seat, without a database or clients. The questions are
+/// generated from the seed
+pub(crate) async fn simulate_game(rules: &GameRules, seed: u64) -> GameResult {
+	let game_rng = GameRng::new(seed);
+	let mut rng = game_rng.fork();
+	let [pd1, pd2, pd3] =
+		SEATS.map(|(_, difficulty)| GamePlayerData::new_bot(difficulty, &mut rng));
+	let mut players = GamePlayerInfo::new();
+	for (player, data) in [
+		(PlayerName::Player1, &pd1),
+		(PlayerName::Player2, &pd2),
+		(PlayerName::Player3, &pd3),
+	] {
+		players.add(player, SGamePlayerInfo::new_bot(data.bot_strategy()));
+	}
+	let info = PlayerInfo {
+		p1_name: "Bot 1".to_string(),
+		p2_name: "Bot 2".to_string(),
+		p3_name: "Bot 3".to_string(),
+		pd1,
+		pd2,
+		pd3,
+		you: "1,2,3".to_string(),
+		game_id: 0,
+		room: "1".to_string(),
+		rules: "0,0".to_string(),
+	};
+
+	let questions = Arc::new(InMemoryQuestions::generated(QUESTIONS, &mut rng));
+	let mut game = TriviadorGame::new_game(info, None, questions, GameMap::hungary(), game_rng);
+	game.rules = rules.clone();
+	game.utils = players;
+	let game = SharedTrivGame::new(game);
+
+	let start = Instant::now();
+	SGame::new(game.arc_clone()).handle_all().await;
+	let length = start.elapsed();
+
+	let state = game.read().await.state.clone();
+	let ranking = ranking(
+		&state.players_points,
+		&state.forfeited_players,
+		&state.tiebreak_ranking,
+	);
+	GameResult {
+		winner: ranking[0],
+		points: PlayerName::all()
+			.map(|player| (player, state.players_points.get_player_points(&player)))
+			.collect(),
+		length,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test(start_paused = true)]
+	async fn bots_finish_a_game() {
+		let result = simulate_game(&GameRules::default(), 1).await;
+		assert!(result.points.values().any(|points| *points > 0));
+		assert!(result.length > Duration::from_secs(60));
+
+		let again = simulate_game(&GameRules::default(), 1).await;
+		assert_eq!(result.points, again.points);
+	}
+
+	#[test]
+	fn simulate_argument() {
+		let args = |args: &[&str]| games_from_args(args.iter().map(|arg| arg.to_string()));
+		assert_eq!(args(&["server"]), None);
+		assert_eq!(args(&["server", "simulate"]), Some(100));
+		assert_eq!(args(&["server", "simulate", "20"]), Some(20));
+	}
+}
@@ -0,0 +1,183 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::storage::sql::SqlStorage;
+use crate::triviador::question::{Question, TipQuestion};
+
+pub(crate) mod sql;
+
+#[derive(sqlx::FromRow, Clone, Debug, PartialEq)]
+pub(crate) struct User {
+	pub id: i32,
+	pub username: String,
+}
+
+/// The progress of a player which is kept between games
+#[derive(sqlx::FromRow, Clone, Debug, PartialEq)]
+pub(crate) struct Profile {
+	pub user_id: i32,
+	pub xp_points: i32,
+	pub xp_level: i16,
+	pub game_count: i32,
+	pub country_id: String,
+	pub castle_level: i16,
+	pub golds: i32,
+}
+
+/// How a seat finished a game
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GameResult {
+	pub seat: i16,
+	/// Not set for robots
+	pub user_id: Option<i32>,
+	/// 1 for the winner
+	pub placing: i16,
+	pub points: i32,
+	/// Left the game, it counts as a loss whatever the placing is
+	pub forfeited: bool,
+	pub xp: i32,
+}
+
+/// Everything the server keeps in a database, implemented for every supported database
+#[async_trait]
+pub(crate) trait Storage: Debug + Send + Sync {
+	/// Brings the schema up to date
+	async fn migrate(&self) -> anyhow::Result<()>;
+
+	async fn choice_question_count(&self) -> anyhow::Result<i64>;
+
+	/// The question at the offset ordered by id
+	async fn choice_question_at(&self, offset: i64) -> anyhow::Result<Question>;
+
+	async fn tip_question_count(&self) -> anyhow::Result<i64>;
+
+	/// The tip question at the offset ordered by id
+	async fn tip_question_at(&self, offset: i64) -> anyhow::Result<TipQuestion>;
+
+	async fn user_by_name(&self, username: &str) -> anyhow::Result<Option<User>>;
+
+	/// Registers the user unless the name is taken, returns the id of the user with the name
+	async fn add_user(&self, username: &str) -> anyhow::Result<i32>;
+
+	async fn profile(&self, user_id: i32) -> anyhow::Result<Option<Profile>>;
+
+	/// Creates or overwrites the profile of the user
+	async fn save_profile(&self, profile: &Profile) -> anyhow::Result<()>;
+
+	/// Records the start of a game and returns its unique id
+	async fn new_game_id(&self) -> anyhow::Result<u32>;
+
+	/// Stores how the seats of a finished game placed
+	async fn save_result(&self, game_id: u32, results: &[GameResult]) -> anyhow::Result<()>;
+
+	/// Stores the replay, the viewers are the users who may watch it
+	async fn save_replay(
+		&self,
+		game_id: u32,
+		seed: u64,
+		events: &str,
+		viewers: &[i32],
+	) -> anyhow::Result<()>;
+
+	/// Whether the user played or watched the game
+	async fn is_replay_viewer(&self, game_id: u32, user_id: i32) -> anyhow::Result<bool>;
+
+	/// The events of the latest recorded replay of the game
+	async fn latest_replay(&self, game_id: u32) -> anyhow::Result<Option<String>>;
+}
+
+pub(crate) type SharedStorage = Arc<dyn Storage>;
+
+/// The id of the user with the name, users are registered on their first login
+pub(crate) async fn login(storage: &SharedStorage, username: &str) -> anyhow::Result<i32> {
+	match storage.user_by_name(username).await? {
+		Some(user) => Ok(user.id),
+		None => storage.add_user(username).await,
+	}
+}
+
+/// Connects to the database of the url and runs its migrations, the scheme of the url selects
+/// the database
+pub(crate) async fn connect(url: &str) -> anyhow::Result<SharedStorage> {
+	let storage: SharedStorage = match url.split_once(':').map(|(scheme, _)| scheme) {
+		Some("postgres" | "postgresql") => {
+			info!("Using Postgres storage");
+			Arc::new(SqlStorage::connect_postgres(url).await?)
+		}
+		Some("sqlite") => {
+			info!("Using SQLite storage");
+			Arc::new(SqlStorage::connect_sqlite(url).await?)
+		}
+		_ => bail!("Unsupported DATABASE_URL, it should start with postgres:// or sqlite:"),
+	};
+	storage.migrate().await?;
+	Ok(storage)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn sqlite_storage_round_trip() {
+		let storage = connect("sqlite::memory:").await.unwrap();
+
+		assert_eq!(storage.tip_question_count().await.unwrap(), 0);
+
+		assert_eq!(storage.user_by_name("lajos").await.unwrap(), None);
+		let lajos = login(&storage, "lajos").await.unwrap();
+		assert_eq!(login(&storage, "lajos").await.unwrap(), lajos);
+		assert_eq!(storage.add_user("lajos").await.unwrap(), lajos);
+		assert_ne!(login(&storage, "felso").await.unwrap(), lajos);
+		assert_eq!(
+			storage.user_by_name("lajos").await.unwrap(),
+			Some(User {
+				id: lajos,
+				username: "lajos".to_string()
+			})
+		);
+
+		let id = 5;
+		assert_eq!(storage.profile(id).await.unwrap(), None);
+		let mut profile = Profile {
+			user_id: id,
+			xp_points: 1200,
+			xp_level: 3,
+			game_count: 10,
+			country_id: "hu".to_string(),
+			castle_level: 2,
+			golds: 3000,
+		};
+		storage.save_profile(&profile).await.unwrap();
+		profile.game_count += 1;
+		profile.golds -= 1500;
+		storage.save_profile(&profile).await.unwrap();
+		assert_eq!(storage.profile(id).await.unwrap(), Some(profile));
+
+		let first = storage.new_game_id().await.unwrap();
+		assert!(storage.new_game_id().await.unwrap() > first);
+
+		storage
+			.save_replay(7, u64::MAX, "[1]", &[id])
+			.await
+			.unwrap();
+		storage.save_replay(7, 1, "[2]", &[id, 6]).await.unwrap();
+		assert!(storage.is_replay_viewer(7, 6).await.unwrap());
+		assert!(!storage.is_replay_viewer(7, 8).await.unwrap());
+		assert!(!storage.is_replay_viewer(8, id).await.unwrap());
+		assert_eq!(
+			storage.latest_replay(7).await.unwrap().as_deref(),
+			Some("[2]")
+		);
+		assert_eq!(storage.latest_replay(8).await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn rejects_unknown_databases() {
+		assert!(connect("mysql://localhost/honfoglalo").await.is_err());
+	}
+}
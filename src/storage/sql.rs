@@ -0,0 +1,324 @@
+use async_trait::async_trait;
+use sqlx::AnyPool;
+use sqlx::any::{AnyPoolOptions, install_default_drivers};
+use sqlx::migrate::Migrator;
+
+use super::{GameResult, Profile, Storage, User};
+use crate::triviador::question::{Question, TipQuestion};
+
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("migrations/postgres");
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("migrations/sqlite");
+
+/// Storage in Postgres or SQLite, the queries are shared as both accept `$1` placeholders
+#[derive(Debug)]
+pub(crate) struct SqlStorage {
+	pool: AnyPool,
+	migrations: &'static Migrator,
+}
+
+impl SqlStorage {
+	pub(crate) async fn connect_postgres(url: &str) -> anyhow::Result<SqlStorage> {
+		SqlStorage::connect(AnyPoolOptions::new(), url, &POSTGRES_MIGRATIONS).await
+	}
+
+	/// Opens the database file of the url, it is created on the first start
+	pub(crate) async fn connect_sqlite(url: &str) -> anyhow::Result<SqlStorage> {
+		let mut options = AnyPoolOptions::new();
+		if url.contains(":memory:") {
+			// every connection would open a database of its own
+			options = options
+				.max_connections(1)
+				.idle_timeout(None)
+				.max_lifetime(None);
+		}
+		SqlStorage::connect(options, &create_if_missing(url), &SQLITE_MIGRATIONS).await
+	}
+
+	async fn connect(
+		options: AnyPoolOptions,
+		url: &str,
+		migrations: &'static Migrator,
+	) -> anyhow::Result<SqlStorage> {
+		install_default_drivers();
+		Ok(SqlStorage {
+			pool: options.connect(url).await?,
+			migrations,
+		})
+	}
+}
+
+/// Adds `mode=rwc` to a SQLite url unless it's in memory or sets the mode itself
+fn create_if_missing(url: &str) -> String {
+	if url.contains(":memory:") || url.contains("mode=") {
+		url.to_string()
+	} else if url.contains('?') {
+		format!("{}&mode=rwc", url)
+	} else {
+		format!("{}?mode=rwc", url)
+	}
+}
+
+#[async_trait]
+impl Storage for SqlStorage {
+	async fn migrate(&self) -> anyhow::Result<()> {
+		self.migrations.run(&self.pool).await?;
+		Ok(())
+	}
+
+	async fn choice_question_count(&self) -> anyhow::Result<i64> {
+		Ok(sqlx::query_scalar("SELECT COUNT(*) FROM choice_questions")
+			.fetch_one(&self.pool)
+			.await?)
+	}
+
+	async fn choice_question_at(&self, offset: i64) -> anyhow::Result<Question> {
+		let (question, answer1, answer2, answer3, answer4, theme, good): (
+			String,
+			String,
+			String,
+			String,
+			String,
+			String,
+			i16,
+		) = sqlx::query_as(
+			r#"SELECT question,
+			answer1,
+			answer2,
+			answer3,
+			answer4,
+			theme,
+			good
+			FROM choice_questions ORDER BY id LIMIT 1 OFFSET $1"#,
+		)
+		.bind(offset)
+		.fetch_one(&self.pool)
+		.await?;
+
+		Ok(Question::new(
+			question,
+			answer1,
+			answer2,
+			answer3,
+			answer4,
+			theme,
+			Some(good as u8),
+		))
+	}
+
+	async fn tip_question_count(&self) -> anyhow::Result<i64> {
+		Ok(sqlx::query_scalar("SELECT COUNT(*) FROM tip_questions")
+			.fetch_one(&self.pool)
+			.await?)
+	}
+
+	async fn tip_question_at(&self, offset: i64) -> anyhow::Result<TipQuestion> {
+		let (question, theme, good): (String, String, i32) = sqlx::query_as(
+			r#"SELECT
+			question,
+			theme,
+			good
+			FROM tip_questions ORDER BY id LIMIT 1 OFFSET $1"#,
+		)
+		.bind(offset)
+		.fetch_one(&self.pool)
+		.await?;
+
+		Ok(TipQuestion::new(question, theme, Some(good)))
+	}
+
+	async fn user_by_name(&self, username: &str) -> anyhow::Result<Option<User>> {
+		Ok(
+			sqlx::query_as("SELECT id, username FROM users WHERE username = $1")
+				.bind(username)
+				.fetch_optional(&self.pool)
+				.await?,
+		)
+	}
+
+	async fn add_user(&self, username: &str) -> anyhow::Result<i32> {
+		// logins aren't password protected yet, the password is left empty
+		Ok(sqlx::query_scalar(
+			r#"INSERT INTO users (username, password) VALUES ($1, '')
+			ON CONFLICT (username) DO UPDATE SET username = excluded.username
+			RETURNING id"#,
+		)
+		.bind(username)
+		.fetch_one(&self.pool)
+		.await?)
+	}
+
+	async fn profile(&self, user_id: i32) -> anyhow::Result<Option<Profile>> {
+		Ok(sqlx::query_as(
+			r#"SELECT user_id, xp_points, xp_level, game_count, country_id, castle_level, golds
+			FROM profiles WHERE user_id = $1"#,
+		)
+		.bind(user_id)
+		.fetch_optional(&self.pool)
+		.await?)
+	}
+
+	async fn save_profile(&self, profile: &Profile) -> anyhow::Result<()> {
+		sqlx::query(
+			r#"INSERT INTO profiles
+			(user_id, xp_points, xp_level, game_count, country_id, castle_level, golds)
+			VALUES ($1, $2, $3, $4, $5, $6, $7)
+			ON CONFLICT (user_id) DO UPDATE SET
+			xp_points = excluded.xp_points,
+			xp_level = excluded.xp_level,
+			game_count = excluded.game_count,
+			country_id = excluded.country_id,
+			castle_level = excluded.castle_level,
+			golds = excluded.golds"#,
+		)
+		.bind(profile.user_id)
+		.bind(profile.xp_points)
+		.bind(profile.xp_level)
+		.bind(profile.game_count)
+		.bind(&profile.country_id)
+		.bind(profile.castle_level)
+		.bind(profile.golds)
+		.execute(&self.pool)
+		.await?;
+		Ok(())
+	}
+
+	async fn new_game_id(&self) -> anyhow::Result<u32> {
+		let id: i32 = sqlx::query_scalar("INSERT INTO games DEFAULT VALUES RETURNING id")
+			.fetch_one(&self.pool)
+			.await?;
+		Ok(u32::try_from(id)?)
+	}
+
+	async fn save_result(&self, game_id: u32, results: &[GameResult]) -> anyhow::Result<()> {
+		let mut tx = self.pool.begin().await?;
+		for result in results {
+			sqlx::query(
+				r#"INSERT INTO game_results
+				(game_id, seat, user_id, placing, points, forfeited, xp)
+				VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+			)
+			.bind(game_id as i32)
+			.bind(result.seat)
+			.bind(result.user_id)
+			.bind(result.placing)
+			.bind(result.points)
+			// SQLite booleans can't be read through the Any driver
+			.bind(result.forfeited as i16)
+			.bind(result.xp)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn save_replay(
+		&self,
+		game_id: u32,
+		seed: u64,
+		events: &str,
+		viewers: &[i32],
+	) -> anyhow::Result<()> {
+		let mut tx = self.pool.begin().await?;
+		sqlx::query("INSERT INTO game_replays (game_id, events, seed) VALUES ($1, $2, $3)")
+			.bind(game_id as i32)
+			.bind(events)
+			// the seed is stored with the same bits
+			.bind(seed as i64)
+			.execute(&mut *tx)
+			.await?;
+		for user_id in viewers {
+			sqlx::query(
+				r#"INSERT INTO replay_viewers (game_id, user_id) VALUES ($1, $2)
+				ON CONFLICT DO NOTHING"#,
+			)
+			.bind(game_id as i32)
+			.bind(user_id)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn is_replay_viewer(&self, game_id: u32, user_id: i32) -> anyhow::Result<bool> {
+		let count: i64 = sqlx::query_scalar(
+			"SELECT COUNT(*) FROM replay_viewers WHERE game_id = $1 AND user_id = $2",
+		)
+		.bind(game_id as i32)
+		.bind(user_id)
+		.fetch_one(&self.pool)
+		.await?;
+		Ok(count > 0)
+	}
+
+	async fn latest_replay(&self, game_id: u32) -> anyhow::Result<Option<String>> {
+		Ok(sqlx::query_scalar(
+			"SELECT events FROM game_replays WHERE game_id = $1 ORDER BY id DESC LIMIT 1",
+		)
+		.bind(game_id as i32)
+		.fetch_optional(&self.pool)
+		.await?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn results_are_stored() {
+		let storage = SqlStorage::connect_sqlite("sqlite::memory:").await.unwrap();
+		storage.migrate().await.unwrap();
+		let results = vec![
+			GameResult {
+				seat: 1,
+				user_id: Some(5),
+				placing: 3,
+				points: 1800,
+				forfeited: true,
+				xp: 0,
+			},
+			GameResult {
+				seat: 2,
+				user_id: None,
+				placing: 1,
+				points: 1200,
+				forfeited: false,
+				xp: 480,
+			},
+		];
+		storage.save_result(3, &results).await.unwrap();
+		assert!(storage.save_result(3, &results).await.is_err());
+
+		let stored: Vec<(i16, Option<i32>, i16, i32, i16, i32)> = sqlx::query_as(
+			r#"SELECT seat, user_id, placing, points, forfeited, xp
+			FROM game_results WHERE game_id = $1 ORDER BY seat"#,
+		)
+		.bind(3)
+		.fetch_all(&storage.pool)
+		.await
+		.unwrap();
+		assert_eq!(
+			stored,
+			vec![(1, Some(5), 3, 1800, 1, 0), (2, None, 1, 1200, 0, 480)]
+		);
+	}
+
+	#[test]
+	fn sqlite_files_are_created() {
+		assert_eq!(
+			create_if_missing("sqlite:data.db"),
+			"sqlite:data.db?mode=rwc"
+		);
+		assert_eq!(
+			create_if_missing("sqlite:data.db?cache=shared"),
+			"sqlite:data.db?cache=shared&mode=rwc"
+		);
+		assert_eq!(
+			create_if_missing("sqlite:data.db?mode=ro"),
+			"sqlite:data.db?mode=ro"
+		);
+		assert_eq!(create_if_missing("sqlite::memory:"), "sqlite::memory:");
+	}
+}
@@ -6,12 +6,18 @@ pub(crate) mod county;
 pub(crate) mod fill_round;
 pub(crate) mod game;
 pub(crate) mod game_player_data;
+pub(crate) mod game_rng;
 pub(crate) mod game_state;
+pub(crate) mod map;
 pub(crate) mod player_info;
 pub(crate) mod player_points;
 pub(crate) mod question;
+pub(crate) mod question_provider;
+pub(crate) mod replay;
 pub(crate) mod round_info;
+pub(crate) mod rules;
 pub(crate) mod selection;
 pub(crate) mod shield_mission;
+pub(crate) mod tip_help;
 pub(crate) mod triviador_state;
 pub(crate) mod war_order;
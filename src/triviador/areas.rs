@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use anyhow::bail;
 use serde::{Serialize, Serializer};
@@ -8,6 +9,7 @@ use tracing::error;
 use super::county::County;
 use super::game::SharedTrivGame;
 use super::game_player_data::PlayerName;
+use super::map::GameMap;
 
 #[derive(Serialize, Clone, PartialEq, Debug)]
 pub enum AreaValue {
@@ -42,6 +44,19 @@ impl Area {
 		self.value == AreaValue::_1000
 	}
 
+	pub(crate) fn is_fortress(&self) -> bool {
+		self.is_fortress
+	}
+
+	/// A fortress absorbs the next successful attack against the area
+	pub(crate) fn fortify(&mut self) {
+		self.is_fortress = true;
+	}
+
+	pub(crate) fn destroy_fortress(&mut self) {
+		self.is_fortress = false;
+	}
+
 	pub(crate) fn get_value(&self) -> &AreaValue {
 		&self.value
 	}
@@ -77,6 +92,7 @@ impl Area {
 		new_owner: PlayerName,
 	) -> Result<(), anyhow::Error> {
 		self.owner = new_owner;
+		self.is_fortress = false;
 		self.upgrade_area();
 		Ok(())
 	}
@@ -186,28 +202,85 @@ impl TryFrom<u8> for AreaValue {
 	}
 }
 
+/// The areas of a map with their owners
 #[derive(PartialEq, Clone, Debug)]
-pub struct Areas(HashMap<County, Area>);
+pub struct Areas {
+	map: Arc<GameMap>,
+	areas: HashMap<County, Area>,
+}
 
 impl Areas {
-	pub(crate) fn new() -> Self {
-		Areas(HashMap::new())
+	pub(crate) fn new(map: Arc<GameMap>) -> Self {
+		Areas {
+			map,
+			areas: HashMap::new(),
+		}
+	}
+
+	/// Parses the encoded areas of the map
+	pub(crate) fn parse(map: Arc<GameMap>, s: &str) -> anyhow::Result<Areas> {
+		let vals = crate::utils::split_string_n(s, 2);
+		let mut rest = Areas::new(map);
+		for (i, county_str) in vals.iter().enumerate() {
+			// increase by 1 because we don't want the 0 value County
+			let county = County::try_from((i as u8) + 1)?;
+			if !rest.map.contains(&county) {
+				bail!("Area {} is not on map {}", county, rest.map.name());
+			}
+			rest.insert(county, Area::deserialize_from_hex(county_str)?);
+		}
+		Ok(rest)
+	}
+
+	pub(crate) fn map(&self) -> &GameMap {
+		&self.map
+	}
+
+	pub(crate) fn shared_map(&self) -> Arc<GameMap> {
+		Arc::clone(&self.map)
 	}
 
 	pub(crate) fn get_areas(&self) -> &HashMap<County, Area> {
-		&self.0
+		&self.areas
 	}
 
 	pub(crate) fn insert(&mut self, county: County, area: Area) {
-		self.0.insert(county, area);
+		self.areas.insert(county, area);
 	}
 
 	pub(crate) fn get_area(&self, available_county: &County) -> Option<&Area> {
-		self.0.get(available_county)
+		self.areas.get(available_county)
 	}
 
 	pub(crate) fn get_area_mut(&mut self, available_county: &County) -> Option<&mut Area> {
-		self.0.get_mut(available_county)
+		self.areas.get_mut(available_county)
+	}
+
+	/// Counties nobody owns yet
+	pub(crate) fn free_counties(&self) -> Vec<County> {
+		self.map
+			.counties()
+			.iter()
+			.copied()
+			.filter(|county| {
+				self.get_area(county)
+					.is_none_or(|area| area.owner == PlayerName::Nobody)
+			})
+			.collect()
+	}
+
+	/// Removes every area of the player, returns the released counties
+	pub(crate) fn release_areas(&mut self, owner: PlayerName) -> Vec<County> {
+		let released: Vec<County> = self
+			.areas
+			.iter()
+			.filter(|(_, area)| area.owner == owner)
+			.map(|(county, _)| *county)
+			.collect();
+		for county in &released {
+			self.areas.remove(county);
+		}
+		released
 	}
 
 	/// Conquer all areas of the old owner
@@ -219,7 +292,7 @@ impl Areas {
 	) -> u16 {
 		let mut total_points: u16 = 0;
 
-		for area in self.0.values_mut() {
+		for area in self.areas.values_mut() {
 			if area.owner == old_owner {
 				total_points += area.value.get_points();
 				area.conquer_area_from_base(new_owner).await.unwrap();
@@ -230,13 +303,10 @@ impl Areas {
 	}
 
 	pub fn serialize(&self) -> String {
-		// later this may not be 38 for different countries
-		let mut serialized = String::with_capacity(38);
-		// start from 1 because we don't want the 0 value County
-		for i in 1..=19 {
-			let county = County::try_from(i).unwrap();
-			let area = self.get_area(&county);
-			match area {
+		let counties = self.map.counties();
+		let mut serialized = String::with_capacity(counties.len() * 2);
+		for county in counties {
+			match self.get_area(county) {
 				None => {
 					serialized.push_str("00");
 				}
@@ -255,29 +325,6 @@ impl From<Areas> for String {
 	}
 }
 
-impl FromStr for Areas {
-	type Err = anyhow::Error;
-
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let vals = crate::utils::split_string_n(s, 2);
-		let mut rest = Areas::new();
-		for (i, county_str) in vals.iter().enumerate() {
-			rest.insert(
-				// increase by 1 because we don't want the 0 value County
-				County::try_from((i as u8) + 1)?,
-				Area::deserialize_from_hex(county_str)?,
-			);
-		}
-		Ok(rest)
-	}
-}
-
-impl From<Vec<(County, Area)>> for Areas {
-	fn from(counties: Vec<(County, Area)>) -> Self {
-		Areas(HashMap::from_iter(counties))
-	}
-}
-
 impl Serialize for Areas {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
@@ -293,27 +340,30 @@ mod tests {
 	use serde_test::{Token, assert_ser_tokens};
 
 	use super::*;
+	use crate::triviador::county::hungary;
 
 	#[test]
 	fn full_area_serialize() {
-		let areas = Areas::from(vec![(
-			County::SzabolcsSzatmarBereg,
+		let mut areas = Areas::new(GameMap::hungary());
+		areas.insert(
+			hungary::SZABOLCS_SZATMAR_BEREG,
 			Area {
 				owner: PlayerName::Player3,
 				is_fortress: false,
 				value: AreaValue::_1000,
 			},
-		)]);
+		);
 		let res: String = areas.into();
 		assert_eq!(res, "00000000000000000000000000000013000000");
 	}
 
 	#[test]
 	fn full_area_deserialize() {
-		let res = Areas::from_str("13434343434342424242434141421112414243").unwrap();
+		let res =
+			Areas::parse(GameMap::hungary(), "13434343434342424242434141421112414243").unwrap();
 
 		assert_eq!(
-			*res.get_area(&County::Pest).unwrap(),
+			*res.get_area(&hungary::PEST).unwrap(),
 			// Area::new(3 as u8, false, AreaValue::_1000)
 			Area {
 				owner: PlayerName::Player3,
@@ -323,7 +373,7 @@ mod tests {
 		);
 
 		assert_eq!(
-			*res.get_area(&County::SzabolcsSzatmarBereg).unwrap(),
+			*res.get_area(&hungary::SZABOLCS_SZATMAR_BEREG).unwrap(),
 			// Area::new(2, false, AreaValue::_1000)
 			Area {
 				owner: PlayerName::Player2,
@@ -333,7 +383,7 @@ mod tests {
 		);
 
 		assert_eq!(
-			*res.get_area(&County::Baranya).unwrap(),
+			*res.get_area(&hungary::BARANYA).unwrap(),
 			// Area::new(1, false, AreaValue::_200
 			Area {
 				owner: PlayerName::Player1,
@@ -343,6 +393,31 @@ mod tests {
 		)
 	}
 
+	#[test]
+	fn free_counties() {
+		let mut areas = Areas::new(GameMap::hungary());
+		areas.insert(
+			hungary::PEST,
+			Area {
+				owner: PlayerName::Player1,
+				is_fortress: false,
+				value: AreaValue::_1000,
+			},
+		);
+		areas.insert(
+			hungary::VAS,
+			Area {
+				owner: PlayerName::Nobody,
+				is_fortress: false,
+				value: AreaValue::Unoccupied,
+			},
+		);
+		let free = areas.free_counties();
+		assert_eq!(free.len(), 18);
+		assert!(!free.contains(&hungary::PEST));
+		assert!(free.contains(&hungary::VAS));
+	}
+
 	#[test]
 	fn area_test() {
 		let area = Area {
@@ -368,5 +443,14 @@ mod tests {
 		};
 		assert_ser_tokens(&area, &[Token::String("00")]);
 		assert_eq!(Area::from_str("00").unwrap(), area);
+
+		let mut area = Area {
+			owner: PlayerName::Player2,
+			is_fortress: false,
+			value: AreaValue::_300,
+		};
+		area.fortify();
+		assert_ser_tokens(&area, &[Token::String("b2")]);
+		assert_eq!(Area::from_str("b2").unwrap(), area);
 	}
 }
@@ -1,9 +1,12 @@
 use std::collections::HashSet;
 
+use rand::Rng;
+use rand::seq::SliceRandom;
 use serde::{Serialize, Serializer};
 
 use super::areas::Areas;
 use super::game_player_data::PlayerName;
+use super::map::GameMap;
 use super::selection::Selection;
 use crate::triviador::county::County;
 
@@ -23,6 +26,17 @@ impl AvailableAreas {
 		self.0.is_empty()
 	}
 
+	/// The counties ordered by id, unlike the set this is the same for every game with the seed
+	pub(crate) fn sorted(&self) -> Vec<County> {
+		let mut counties: Vec<County> = self.0.iter().copied().collect();
+		counties.sort();
+		counties
+	}
+
+	pub(crate) fn choose(&self, rng: &mut impl Rng) -> Option<County> {
+		self.sorted().choose(rng).copied()
+	}
+
 	/// Separates the areas into two sets, one for the player and one for the other players
 	fn separate_areas(areas: &Areas, player_id: PlayerName) -> (AvailableAreas, AvailableAreas) {
 		let mut player_areas = AvailableAreas::new();
@@ -38,13 +52,12 @@ impl AvailableAreas {
 		(player_areas, other_areas)
 	}
 
-	fn get_neighbouring_areas(&self) -> AvailableAreas {
+	fn get_neighbouring_areas(&self, map: &GameMap) -> AvailableAreas {
 		let mut filtered_areas = AvailableAreas::new();
-		let all_areas = AvailableAreas::all_counties();
 
-		for county in all_areas.0.iter() {
+		for county in map.counties() {
 			for player_county in self.0.iter() {
-				if player_county.is_neighbour(*county) {
+				if map.is_neighbour(player_county, county) {
 					filtered_areas.0.insert(*county);
 				}
 			}
@@ -84,27 +97,35 @@ impl AvailableAreas {
 
 	pub(crate) fn get_base_areas(areas: &Areas, rel_id: PlayerName) -> AvailableAreas {
 		let (_, other_areas) = Self::separate_areas(areas, rel_id);
-		let excluded = Self::get_neighbouring_areas(&other_areas);
-		let mut player_areas = Self::all_counties();
+		let excluded = other_areas.get_neighbouring_areas(areas.map());
+		let mut player_areas = Self::all_counties(areas.map());
 		player_areas.0.retain(|p| !excluded.0.contains(p));
 		player_areas.filter_occupied_areas(areas);
 		player_areas
 	}
 
+	/// Free counties next to the areas of the player, without falling back to every free county
+	pub(crate) fn get_adjacent_free_areas(areas: &Areas, rel_id: PlayerName) -> AvailableAreas {
+		let (player_areas, _) = Self::separate_areas(areas, rel_id);
+		let mut neighbouring = player_areas.get_neighbouring_areas(areas.map());
+		neighbouring.filter_occupied_areas(areas);
+		neighbouring
+	}
+
 	pub(crate) fn get_conquerable_areas(
 		areas: &Areas,
 		selection: &Selection,
 		rel_player_id: PlayerName,
 	) -> AvailableAreas {
 		let (mut player_areas, _) = Self::separate_areas(areas, rel_player_id);
-		player_areas = Self::get_neighbouring_areas(&player_areas);
+		player_areas = player_areas.get_neighbouring_areas(areas.map());
 		player_areas.filter_occupied_areas(areas);
 		player_areas.filter_selected_areas(selection);
 
 		// if there are no filtered available areas, but there are still free areas return all
 		// unoccupied areas
 		if player_areas.counties().is_empty() {
-			player_areas = AvailableAreas::all_counties();
+			player_areas = AvailableAreas::all_counties(areas.map());
 			player_areas.filter_occupied_areas(areas);
 			player_areas.filter_selected_areas(selection);
 		}
@@ -114,7 +135,7 @@ impl AvailableAreas {
 
 	pub(crate) fn get_attackable_areas(areas: &Areas, rel_id: PlayerName) -> AvailableAreas {
 		let (player_areas, _) = Self::separate_areas(areas, rel_id);
-		let mut neighbouring = Self::get_neighbouring_areas(&player_areas);
+		let mut neighbouring = player_areas.get_neighbouring_areas(areas.map());
 		// remove player areas
 		neighbouring.filter_player_areas(areas, rel_id);
 		neighbouring
@@ -129,28 +150,8 @@ impl AvailableAreas {
 		self.0.insert(county)
 	}
 
-	pub(crate) fn all_counties() -> AvailableAreas {
-		AvailableAreas(HashSet::from([
-			County::Pest,
-			County::Nograd,
-			County::Heves,
-			County::JaszNagykunSzolnok,
-			County::BacsKiskun,
-			County::Fejer,
-			County::KomaromEsztergom,
-			County::Borsod,
-			County::HajduBihar,
-			County::Bekes,
-			County::Csongrad,
-			County::Tolna,
-			County::Somogy,
-			County::Veszprem,
-			County::GyorMosonSopron,
-			County::SzabolcsSzatmarBereg,
-			County::Baranya,
-			County::Zala,
-			County::Vas,
-		]))
+	pub(crate) fn all_counties(map: &GameMap) -> AvailableAreas {
+		AvailableAreas(map.counties().iter().copied().collect())
 	}
 
 	pub fn encode_available_areas(areas: Vec<i32>) -> String {
@@ -187,7 +188,7 @@ impl Serialize for AvailableAreas {
 		let res = self
 			.counties()
 			.iter()
-			.map(|&county| county as i32)
+			.map(|&county| i32::from(county.id()))
 			.collect();
 		s.serialize_str(&Self::encode_available_areas(res))
 	}
@@ -195,11 +196,10 @@ impl Serialize for AvailableAreas {
 
 #[cfg(test)]
 mod tests {
-	use std::str::FromStr;
-
 	use pretty_assertions::assert_eq;
 
 	use super::*;
+	use crate::triviador::county::hungary;
 	use crate::triviador::game_player_data::PlayerName;
 
 	pub fn decode_available_areas(available: i32) -> Vec<i32> {
@@ -215,7 +215,8 @@ mod tests {
 	#[test]
 	fn get_limited_available() {
 		// Szabolcs-Szatmár-Bereg
-		let areas = Areas::from_str("00000000000000000000000000000011000000").unwrap();
+		let areas =
+			Areas::parse(GameMap::hungary(), "00000000000000000000000000000011000000").unwrap();
 
 		let mut selection = Selection::new();
 
@@ -223,118 +224,123 @@ mod tests {
 			AvailableAreas::get_conquerable_areas(&areas, &selection, PlayerName::Player1);
 
 		assert_eq!(available.0.len(), 2);
-		assert!(available.0.contains(&County::Borsod));
-		assert!(available.0.contains(&County::HajduBihar));
+		assert!(available.0.contains(&hungary::BORSOD));
+		assert!(available.0.contains(&hungary::HAJDU_BIHAR));
 
 		let p2_available = AvailableAreas::get_base_areas(&areas, PlayerName::Player2);
 
 		assert_eq!(p2_available.0.len(), 16);
-		assert!(!p2_available.0.contains(&County::SzabolcsSzatmarBereg));
-		assert!(!p2_available.0.contains(&County::Borsod));
-		assert!(!p2_available.0.contains(&County::HajduBihar));
+		assert!(!p2_available.0.contains(&hungary::SZABOLCS_SZATMAR_BEREG));
+		assert!(!p2_available.0.contains(&hungary::BORSOD));
+		assert!(!p2_available.0.contains(&hungary::HAJDU_BIHAR));
 
-		let areas = Areas::from_str("00001100000000000000000000000000000000").unwrap();
-		selection.add_selection(PlayerName::Player2, County::SzabolcsSzatmarBereg);
+		let areas =
+			Areas::parse(GameMap::hungary(), "00001100000000000000000000000000000000").unwrap();
+		selection.add_selection(PlayerName::Player2, hungary::SZABOLCS_SZATMAR_BEREG);
 		let p2_available =
 			AvailableAreas::get_conquerable_areas(&areas, &selection, PlayerName::Player2);
 		assert_eq!(p2_available.0.len(), 17);
-		assert!(!p2_available.0.contains(&County::Heves));
+		assert!(!p2_available.0.contains(&hungary::HEVES));
 		// because it is selected
-		assert!(!p2_available.0.contains(&County::SzabolcsSzatmarBereg));
+		assert!(!p2_available.0.contains(&hungary::SZABOLCS_SZATMAR_BEREG));
 	}
 
 	#[test]
 	fn separate_areas() {
-		let areas = Areas::from_str("11000000000000120000000000130000000000").unwrap();
+		let areas =
+			Areas::parse(GameMap::hungary(), "11000000000000120000000000130000000000").unwrap();
 
 		let sep = AvailableAreas::separate_areas(&areas, PlayerName::Player1);
 
-		assert_eq!(sep.0, AvailableAreas::from(vec![County::Pest]));
+		assert_eq!(sep.0, AvailableAreas::from(vec![hungary::PEST]));
 		assert_eq!(
 			sep.1,
-			AvailableAreas::from(vec![County::Borsod, County::Veszprem,])
+			AvailableAreas::from(vec![hungary::BORSOD, hungary::VESZPREM,])
 		);
 	}
 
 	#[test]
 	fn get_neighbouring_areas() {
-		let player_areas = AvailableAreas::from(vec![County::Pest]);
+		let player_areas = AvailableAreas::from(vec![hungary::PEST]);
 
-		let filtered = AvailableAreas::get_neighbouring_areas(&player_areas);
+		let filtered = player_areas.get_neighbouring_areas(&GameMap::hungary());
 
 		assert_eq!(
 			filtered,
 			AvailableAreas::from(vec![
-				County::Nograd,
-				County::Heves,
-				County::JaszNagykunSzolnok,
-				County::BacsKiskun,
-				County::Fejer,
-				County::KomaromEsztergom,
+				hungary::NOGRAD,
+				hungary::HEVES,
+				hungary::JASZ_NAGYKUN_SZOLNOK,
+				hungary::BACS_KISKUN,
+				hungary::FEJER,
+				hungary::KOMAROM_ESZTERGOM,
 			])
 		);
 
-		assert!(!filtered.0.contains(&County::Pest));
+		assert!(!filtered.0.contains(&hungary::PEST));
 
-		let player_areas = AvailableAreas::from(vec![County::Pest, County::SzabolcsSzatmarBereg]);
+		let player_areas =
+			AvailableAreas::from(vec![hungary::PEST, hungary::SZABOLCS_SZATMAR_BEREG]);
 
-		let filtered = AvailableAreas::get_neighbouring_areas(&player_areas);
+		let filtered = player_areas.get_neighbouring_areas(&GameMap::hungary());
 
 		assert_eq!(
 			filtered,
 			AvailableAreas::from(vec![
-				County::Nograd,
-				County::Heves,
-				County::JaszNagykunSzolnok,
-				County::BacsKiskun,
-				County::Fejer,
-				County::KomaromEsztergom,
-				County::Borsod,
-				County::HajduBihar,
+				hungary::NOGRAD,
+				hungary::HEVES,
+				hungary::JASZ_NAGYKUN_SZOLNOK,
+				hungary::BACS_KISKUN,
+				hungary::FEJER,
+				hungary::KOMAROM_ESZTERGOM,
+				hungary::BORSOD,
+				hungary::HAJDU_BIHAR,
 			])
 		);
 
-		assert!(!filtered.0.contains(&County::Pest));
-		assert!(!filtered.0.contains(&County::SzabolcsSzatmarBereg));
+		assert!(!filtered.0.contains(&hungary::PEST));
+		assert!(!filtered.0.contains(&hungary::SZABOLCS_SZATMAR_BEREG));
 	}
 
 	#[test]
 	fn attackable_areas() {
-		let areas = Areas::from_str("11414241414142124342134342424243414343").unwrap();
-		// let player_areas = AvailableAreas::from(vec![County::Pest]);
+		let areas =
+			Areas::parse(GameMap::hungary(), "11414241414142124342134342424243414343").unwrap();
+		// let player_areas = AvailableAreas::from(vec![hungary::PEST]);
 
 		let filtered = AvailableAreas::get_attackable_areas(&areas, PlayerName::Player1);
 
-		assert!(filtered.counties().contains(&County::Heves));
-		assert!(filtered.counties().contains(&County::Borsod));
-		assert!(filtered.counties().contains(&County::HajduBihar));
-		assert!(filtered.counties().contains(&County::Bekes));
-		assert!(filtered.counties().contains(&County::Csongrad));
-		assert!(filtered.counties().contains(&County::Tolna));
-		assert!(filtered.counties().contains(&County::Somogy));
-		assert!(filtered.counties().contains(&County::Veszprem));
-		assert!(filtered.counties().contains(&County::KomaromEsztergom));
-		assert!(!filtered.counties().contains(&County::Pest));
-		assert!(!filtered.counties().contains(&County::Fejer));
-		assert!(!filtered.counties().contains(&County::BacsKiskun));
-		assert!(!filtered.counties().contains(&County::Baranya));
-		assert!(!filtered.counties().contains(&County::JaszNagykunSzolnok));
-		assert!(!filtered.counties().contains(&County::Nograd));
+		assert!(filtered.counties().contains(&hungary::HEVES));
+		assert!(filtered.counties().contains(&hungary::BORSOD));
+		assert!(filtered.counties().contains(&hungary::HAJDU_BIHAR));
+		assert!(filtered.counties().contains(&hungary::BEKES));
+		assert!(filtered.counties().contains(&hungary::CSONGRAD));
+		assert!(filtered.counties().contains(&hungary::TOLNA));
+		assert!(filtered.counties().contains(&hungary::SOMOGY));
+		assert!(filtered.counties().contains(&hungary::VESZPREM));
+		assert!(filtered.counties().contains(&hungary::KOMAROM_ESZTERGOM));
+		assert!(!filtered.counties().contains(&hungary::PEST));
+		assert!(!filtered.counties().contains(&hungary::FEJER));
+		assert!(!filtered.counties().contains(&hungary::BACS_KISKUN));
+		assert!(!filtered.counties().contains(&hungary::BARANYA));
+		assert!(!filtered.counties().contains(&hungary::JASZ_NAGYKUN_SZOLNOK));
+		assert!(!filtered.counties().contains(&hungary::NOGRAD));
 	}
 	#[test]
 	fn deserializer() {
-		let areas = Areas::from_str("11000000000000120000000000130000000000").unwrap();
+		let areas =
+			Areas::parse(GameMap::hungary(), "11000000000000120000000000130000000000").unwrap();
 
 		assert_eq!(
-			areas.get_area(&County::Pest).unwrap().owner,
+			areas.get_area(&hungary::PEST).unwrap().owner,
 			PlayerName::Player1
 		);
 		assert_eq!(
-			areas.get_area(&County::Borsod).unwrap().owner,
+			areas.get_area(&hungary::BORSOD).unwrap().owner,
 			PlayerName::Player2
 		);
 		assert_eq!(
-			areas.get_area(&County::Veszprem).unwrap().owner,
+			areas.get_area(&hungary::VESZPREM).unwrap().owner,
 			PlayerName::Player3
 		);
 	}
@@ -355,4 +361,20 @@ mod tests {
 			"077FFF"
 		)
 	}
+
+	#[test]
+	fn choice_does_not_depend_on_set_order() {
+		use rand::SeedableRng;
+		use rand::rngs::StdRng;
+
+		let counties: Vec<County> = (1..=19).map(|id| County::try_from(id).unwrap()).collect();
+		let mut reversed = counties.clone();
+		reversed.reverse();
+		for seed in 0..20 {
+			assert_eq!(
+				AvailableAreas::from(counties.clone()).choose(&mut StdRng::seed_from_u64(seed)),
+				AvailableAreas::from(reversed.clone()).choose(&mut StdRng::seed_from_u64(seed))
+			);
+		}
+	}
 }
@@ -26,6 +26,15 @@ impl Base {
 		self.towers_destroyed += 1;
 	}
 
+	/// Rebuilds a destroyed tower, returns false if every tower is standing
+	pub fn build_tower(&mut self) -> bool {
+		if self.towers_destroyed == 0 {
+			return false;
+		}
+		self.towers_destroyed -= 1;
+		true
+	}
+
 	pub fn tower_count(&self) -> u8 {
 		3 - self.towers_destroyed
 	}
@@ -96,6 +105,10 @@ impl Bases {
 		Ok(())
 	}
 
+	pub(crate) fn remove_base(&mut self, player: &PlayerName) -> Option<Base> {
+		self.every_base.remove(player)
+	}
+
 	pub(crate) fn get_base(&self, player: &PlayerName) -> Option<&Base> {
 		self.every_base.get(player)
 	}
@@ -147,6 +160,10 @@ mod tests {
 		base.destroy_tower();
 		assert_eq!(base.serialize_to_hex(), "82");
 		assert_eq!(Base::from_str("82").unwrap(), base);
+		assert!(base.build_tower());
+		assert_eq!(base.tower_count(), 2);
+		assert!(base.build_tower());
+		assert!(!base.build_tower());
 
 		let base = Base::new(8);
 
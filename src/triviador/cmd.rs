@@ -1,7 +1,10 @@
+use std::time::Duration;
+
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
 use super::{game::SharedTrivGame, game_player_data::PlayerName};
+use crate::config::config;
 use crate::triviador::available_area::AvailableAreas;
 
 #[skip_serializing_none]
@@ -20,6 +23,30 @@ pub struct Cmd {
 }
 
 impl Cmd {
+	/// Seconds the server waits on top of the client timeout, so slow connections can still
+	/// answer in time
+	const GRACE_PERIOD: u64 = 3;
+
+	/// Seconds a player has to select an area
+	pub(crate) fn select_timeout() -> u8 {
+		config().game.select_timeout
+	}
+
+	/// Seconds a player has to answer a question
+	pub(crate) fn answer_timeout() -> u8 {
+		config().game.answer_timeout
+	}
+
+	/// Seconds a player has to give a tip
+	pub(crate) fn tip_timeout() -> u8 {
+		config().game.tip_timeout
+	}
+
+	/// The time the server waits for a command with the given client timeout
+	pub(crate) fn deadline(timeout: u8) -> Duration {
+		Duration::from_secs(u64::from(timeout) + Self::GRACE_PERIOD)
+	}
+
 	pub(crate) fn select_command(available_areas: AvailableAreas, timeout: u8) -> Cmd {
 		Cmd {
 			command: "SELECT".to_string(),
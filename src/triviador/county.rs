@@ -1,174 +1,24 @@
-use std::collections::HashSet;
 use std::fmt;
-use std::str::FromStr;
 
 use anyhow::bail;
 use serde::Serialize;
-use tracing::error;
 
-#[derive(Serialize, Clone, Copy, Eq, PartialEq, PartialOrd, Hash, Debug)]
-// todo make this country based
-pub enum County {
-	NoResponse = 0,            // if nothing is selected
-	Pest = 1,                  // Pest
-	Nograd = 2,                // Nógrád
-	Heves = 3,                 // Heves
-	JaszNagykunSzolnok = 4,    // Jász-Nagykun-Szolnok
-	BacsKiskun = 5,            // Bács-Kiskun
-	Fejer = 6,                 // Fejér
-	KomaromEsztergom = 7,      // Komárom-Esztergom
-	Borsod = 8,                // Borsod
-	HajduBihar = 9,            // Hajdú-Bihar
-	Bekes = 10,                // Békés
-	Csongrad = 11,             // Csongrád
-	Tolna = 12,                // Tolna
-	Somogy = 13,               // Somogy
-	Veszprem = 14,             // Veszprém
-	GyorMosonSopron = 15,      // Győr-Moson-Sopron
-	SzabolcsSzatmarBereg = 16, // Szabolcs-Szatmár-Bereg
-	Baranya = 17,              // Baranya
-	Zala = 18,                 // Zala
-	Vas = 19,                  // Vas
-}
+/// An area of a map by its id, the ids of a map start from 1
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash, Debug)]
+pub struct County(u8);
 
 impl County {
-	pub fn county_neighbours(&self) -> HashSet<County> {
-		let mut hs = HashSet::with_capacity(2);
-		match self {
-			County::NoResponse => {
-				// todo check this out
-				error!("NoResponse county has NO neighbours");
-			}
-			County::Pest => {
-				hs.insert(County::Nograd);
-				hs.insert(County::Heves);
-				hs.insert(County::JaszNagykunSzolnok);
-				hs.insert(County::BacsKiskun);
-				hs.insert(County::Fejer);
-				hs.insert(County::KomaromEsztergom);
-			}
-			County::Nograd => {
-				hs.insert(County::Pest);
-				hs.insert(County::Heves);
-				hs.insert(County::Borsod);
-			}
-			County::Heves => {
-				hs.insert(County::Pest);
-				hs.insert(County::Nograd);
-				hs.insert(County::Borsod);
-				hs.insert(County::JaszNagykunSzolnok);
-			}
-			County::JaszNagykunSzolnok => {
-				hs.insert(County::Pest);
-				hs.insert(County::Heves);
-				hs.insert(County::Borsod);
-				hs.insert(County::HajduBihar);
-				hs.insert(County::Bekes);
-				hs.insert(County::Csongrad);
-				hs.insert(County::BacsKiskun);
-			}
-			County::BacsKiskun => {
-				hs.insert(County::Tolna);
-				hs.insert(County::Baranya);
-				hs.insert(County::Fejer);
-				hs.insert(County::Pest);
-				hs.insert(County::JaszNagykunSzolnok);
-				hs.insert(County::Csongrad);
-			}
-			County::Fejer => {
-				hs.insert(County::KomaromEsztergom);
-				hs.insert(County::Pest);
-				hs.insert(County::BacsKiskun);
-				hs.insert(County::Tolna);
-				hs.insert(County::Somogy);
-				hs.insert(County::Veszprem);
-			}
-			County::KomaromEsztergom => {
-				hs.insert(County::Pest);
-				hs.insert(County::Fejer);
-				hs.insert(County::Veszprem);
-				hs.insert(County::GyorMosonSopron);
-			}
-			County::Borsod => {
-				hs.insert(County::SzabolcsSzatmarBereg);
-				hs.insert(County::HajduBihar);
-				hs.insert(County::JaszNagykunSzolnok);
-				hs.insert(County::Heves);
-				hs.insert(County::Nograd);
-			}
-			County::HajduBihar => {
-				hs.insert(County::SzabolcsSzatmarBereg);
-				hs.insert(County::Bekes);
-				hs.insert(County::JaszNagykunSzolnok);
-				hs.insert(County::Borsod);
-			}
-			County::Bekes => {
-				hs.insert(County::HajduBihar);
-				hs.insert(County::Csongrad);
-				hs.insert(County::JaszNagykunSzolnok);
-			}
-			County::Csongrad => {
-				hs.insert(County::JaszNagykunSzolnok);
-				hs.insert(County::Bekes);
-				hs.insert(County::BacsKiskun);
-			}
-			County::Tolna => {
-				hs.insert(County::Fejer);
-				hs.insert(County::BacsKiskun);
-				hs.insert(County::Baranya);
-				hs.insert(County::Somogy);
-			}
-			County::Somogy => {
-				hs.insert(County::Veszprem);
-				hs.insert(County::Fejer);
-				hs.insert(County::Tolna);
-				hs.insert(County::Baranya);
-				hs.insert(County::Zala);
-			}
-			County::Veszprem => {
-				hs.insert(County::GyorMosonSopron);
-				hs.insert(County::KomaromEsztergom);
-				hs.insert(County::Fejer);
-				hs.insert(County::Somogy);
-				hs.insert(County::Zala);
-				hs.insert(County::Vas);
-			}
-			County::GyorMosonSopron => {
-				hs.insert(County::KomaromEsztergom);
-				hs.insert(County::Veszprem);
-				hs.insert(County::Vas);
-			}
-			County::SzabolcsSzatmarBereg => {
-				hs.insert(County::Borsod);
-				hs.insert(County::HajduBihar);
-			}
-			County::Baranya => {
-				hs.insert(County::Tolna);
-				hs.insert(County::BacsKiskun);
-				hs.insert(County::Somogy);
-			}
-			County::Zala => {
-				hs.insert(County::Vas);
-				hs.insert(County::Veszprem);
-				hs.insert(County::Somogy);
-			}
-			County::Vas => {
-				hs.insert(County::GyorMosonSopron);
-				hs.insert(County::Veszprem);
-				hs.insert(County::Zala);
-			}
-		}
-		hs
-	}
+	/// The available areas are encoded on 30 bits
+	pub(crate) const MAX_ID: u8 = 30;
 
-	pub fn is_neighbour(&self, other: County) -> bool {
-		self.county_neighbours().contains(&other)
+	pub(crate) fn id(&self) -> u8 {
+		self.0
 	}
 }
 
 impl fmt::Display for County {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{:?}", self)
+		write!(f, "{}", self.0)
 	}
 }
 
@@ -176,59 +26,41 @@ impl TryFrom<u8> for County {
 	type Error = anyhow::Error;
 
 	fn try_from(value: u8) -> Result<Self, Self::Error> {
-		let res = match value {
-			0 => County::NoResponse,
-			1 => County::Pest,
-			2 => County::Nograd,
-			3 => County::Heves,
-			4 => County::JaszNagykunSzolnok,
-			5 => County::BacsKiskun,
-			6 => County::Fejer,
-			7 => County::KomaromEsztergom,
-			8 => County::Borsod,
-			9 => County::HajduBihar,
-			10 => County::Bekes,
-			11 => County::Csongrad,
-			12 => County::Tolna,
-			13 => County::Somogy,
-			14 => County::Veszprem,
-			15 => County::GyorMosonSopron,
-			16 => County::SzabolcsSzatmarBereg,
-			17 => County::Baranya,
-			18 => County::Zala,
-			19 => County::Vas,
-			_ => bail!("Invalid county number"),
-		};
-		Ok(res)
+		if value == 0 || value > Self::MAX_ID {
+			bail!("Invalid county number");
+		}
+		Ok(County(value))
 	}
 }
 
-impl FromStr for County {
-	type Err = anyhow::Error;
-
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		match s {
-			"NoResponse" => Ok(County::NoResponse),
-			"Pest" => Ok(County::Pest),
-			"Nograd" => Ok(County::Nograd),
-			"Heves" => Ok(County::Heves),
-			"JaszNagykunSzolnok" => Ok(County::JaszNagykunSzolnok),
-			"BacsKiskun" => Ok(County::BacsKiskun),
-			"Fejer" => Ok(County::Fejer),
-			"KomaromEsztergom" => Ok(County::KomaromEsztergom),
-			"Borsod" => Ok(County::Borsod),
-			"HajduBihar" => Ok(County::HajduBihar),
-			"Bekes" => Ok(County::Bekes),
-			"Csongrad" => Ok(County::Csongrad),
-			"Tolna" => Ok(County::Tolna),
-			"Somogy" => Ok(County::Somogy),
-			"Veszprem" => Ok(County::Veszprem),
-			"GyorMosonSopron" => Ok(County::GyorMosonSopron),
-			"SzabolcsSzatmarBereg" => Ok(County::SzabolcsSzatmarBereg),
-			"Baranya" => Ok(County::Baranya),
-			"Zala" => Ok(County::Zala),
-			"Vas" => Ok(County::Vas),
-			_ => bail!("Invalid county name"),
-		}
+impl From<County> for u8 {
+	fn from(county: County) -> Self {
+		county.0
 	}
 }
+
+/// The counties of the Hungarian map by their ids, for tests
+#[cfg(test)]
+pub(crate) mod hungary {
+	use super::County;
+
+	pub(crate) const PEST: County = County(1);
+	pub(crate) const NOGRAD: County = County(2);
+	pub(crate) const HEVES: County = County(3);
+	pub(crate) const JASZ_NAGYKUN_SZOLNOK: County = County(4);
+	pub(crate) const BACS_KISKUN: County = County(5);
+	pub(crate) const FEJER: County = County(6);
+	pub(crate) const KOMAROM_ESZTERGOM: County = County(7);
+	pub(crate) const BORSOD: County = County(8);
+	pub(crate) const HAJDU_BIHAR: County = County(9);
+	pub(crate) const BEKES: County = County(10);
+	pub(crate) const CSONGRAD: County = County(11);
+	pub(crate) const TOLNA: County = County(12);
+	pub(crate) const SOMOGY: County = County(13);
+	pub(crate) const VESZPREM: County = County(14);
+	pub(crate) const GYOR_MOSON_SOPRON: County = County(15);
+	pub(crate) const SZABOLCS_SZATMAR_BEREG: County = County(16);
+	pub(crate) const BARANYA: County = County(17);
+	pub(crate) const ZALA: County = County(18);
+	pub(crate) const VAS: County = County(19);
+}
@@ -1,23 +1,37 @@
 use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::bail;
+use rand::rngs::StdRng;
 use serde::Serialize;
 use serde_with::skip_serializing_none;
-use sqlx::PgPool;
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
-use tracing::trace;
+use tracing::{info, trace, warn};
 
 use super::areas::Areas;
 use super::available_area::AvailableAreas;
 use super::fill_round::FillRound;
 use super::game_player_data::PlayerName;
 use super::player_points::PlayerPoints;
+use crate::app::{GamePlayerChannels, ListenPlayerChannel};
+use crate::channels::ChannelErrorResponse;
+use crate::config::config;
+use crate::game_handlers::endscreen_handler::GameReward;
 use crate::game_handlers::s_game::GamePlayerInfo;
 use crate::game_handlers::{send_player_commongame, wait_for_game_ready};
+use crate::storage::{GameResult, Profile, SharedStorage};
 use crate::triviador::bases::Bases;
 use crate::triviador::cmd::Cmd;
+use crate::triviador::game_rng::GameRng;
+use crate::triviador::map::GameMap;
+use crate::triviador::question_provider::SharedQuestionProvider;
+use crate::triviador::replay::ReplayLog;
+use crate::triviador::rules::{ForfeitPolicy, GameRules};
 use crate::triviador::selection::Selection;
+use crate::triviador::tip_help::UsedHelps;
 use crate::triviador::{
 	game_state::GameState, player_info::PlayerInfo, round_info::RoundInfo,
 	triviador_state::TriviadorState,
@@ -27,7 +41,14 @@ use crate::users::ServerCommand;
 #[derive(Debug)]
 pub struct SharedTrivGame(Arc<RwLock<TriviadorGame>>);
 
+impl Clone for SharedTrivGame {
+	fn clone(&self) -> Self {
+		self.arc_clone()
+	}
+}
+
 impl SharedTrivGame {
+	/// A full listen channel is not emptied by a disconnected player
 	pub fn new(game: TriviadorGame) -> Self {
 		Self(Arc::new(RwLock::new(game)))
 	}
@@ -37,6 +58,11 @@ impl SharedTrivGame {
 		Self(Arc::clone(&self.0))
 	}
 
+	/// Whether both handles point to the same game
+	pub(crate) fn ptr_eq(&self, other: &SharedTrivGame) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+
 	pub(crate) async fn read(&self) -> tokio::sync::RwLockReadGuard<'_, TriviadorGame> {
 		self.0.read().await
 	}
@@ -45,66 +71,331 @@ impl SharedTrivGame {
 		self.0.write().await
 	}
 
-	// todo better error handling
+	/// A generator for a single random decision of the game
+	pub(crate) async fn rng(&self) -> StdRng {
+		self.read().await.rng.fork()
+	}
+
+	/// Sends a message to the player, if the player doesn't take it from the channel in time
+	/// they are considered disconnected
 	pub(crate) async fn send_xml_channel(
 		&self,
 		player: &PlayerName,
 		msg: String,
-	) -> Result<(), flume::SendError<String>> {
+	) -> anyhow::Result<()> {
 		let game = self.read().await;
 		let channel = game
 			.utils
 			.get_player(player)
-			.unwrap()
-			.get_player_channels()
-			.clone();
+			.and_then(|info| info.get_player_channels().clone());
+		drop(game);
+		let Some(channel) = channel else {
+			bail!("Channels not found for player: {:?}", player);
+		};
+		match tokio::time::timeout(
+			config().game.send_timeout(),
+			channel.xml_channel.send_message(msg),
+		)
+		.await
+		{
+			Ok(Ok(())) => Ok(()),
+			Ok(Err(_)) => {
+				self.player_disconnected(player).await;
+				bail!("The listen channel of player {:?} is closed", player)
+			}
+			Err(_) => {
+				self.player_disconnected(player).await;
+				bail!("Timeout sending message to player: {:?}", player)
+			}
+		}
+	}
+
+	/// Gives the seat back to a player who logged in again and sends them the current state with
+	/// the command they haven't answered yet
+	pub(crate) async fn rejoin_player(
+		&self,
+		user_id: i32,
+		channels: GamePlayerChannels,
+	) -> anyhow::Result<()> {
+		let mut game = self.write().await;
+		let Some(player) = game
+			.players
+			.as_ref()
+			.and_then(|players| players.get_player_by_id(user_id))
+		else {
+			bail!("User {} doesn't play in this game", user_id);
+		};
+		if game.state.forfeited_players.contains(&player) {
+			bail!("Player {:?} left the game", player);
+		}
+		let Some(info) = game.utils.get_player_mut(&player) else {
+			bail!("No info found for player: {:?}", player);
+		};
+		// a receiver might still wait on the old channels
+		if let Some(old) = info.get_player_channels() {
+			let _ = old
+				.command_channel
+				.try_send_message(ServerCommand::ChannelsReplaced);
+		}
+		info.rejoin(channels);
+		game.state.players_connected = game.utils.connected_players();
+		drop(game);
+
+		info!("User {} rejoined the game as {:?}", user_id, player);
+		send_player_commongame(self, &player).await;
+		Ok(())
+	}
+
+	/// The player left the game, they are eliminated and placed last on the end screen. Their seat
+	/// is handled by the forfeit policy: a bot plays it on, or its territory is freed
+	pub(crate) async fn forfeit_player(&self, user_id: i32) -> anyhow::Result<PlayerName> {
+		let mut game = self.write().await;
+		let Some(player) = game
+			.players
+			.as_ref()
+			.and_then(|players| players.get_player_by_id(user_id))
+		else {
+			bail!("User {} doesn't play in this game", user_id);
+		};
+		if game.state.forfeited_players.contains(&player) {
+			bail!("Player {:?} already left the game", player);
+		}
+
+		game.state.forfeited_players.push(player);
+		if !game.state.eliminated_players.contains(&player) {
+			game.state.eliminated_players.push(player);
+		}
+		// the seat is answered for by a bot, under LeaveNeutral it has nothing left to play
+		if let Some(info) = game.utils.get_player_mut(&player) {
+			info.disconnect();
+		}
+		game.state.players_connected = game.utils.connected_players();
+
+		match game.rules.forfeit_policy {
+			ForfeitPolicy::KeepWithBot => game.state.bot_seats.push(player),
+			ForfeitPolicy::LeaveNeutral => {
+				let released = game.state.areas_info.release_areas(player);
+				game.state.base_info.remove_base(&player);
+				game.state.players_points.set_player_points(&player, 0);
+				// the free areas are handed out again while filling the map
+				if game.state.game_state.state == 3 {
+					for county in released {
+						game.state.available_areas.push_county(county);
+					}
+				}
+			}
+		}
+		info!("Player {:?} left the game", player);
+		Ok(player)
+	}
+
+	/// Attaches a read only listen channel to the game and sends the current state to it
+	pub(crate) async fn add_spectator(&self, user_id: i32, channel: ListenPlayerChannel) {
+		let mut game = self.write().await;
+		let mut public = game.clone();
+		public.cmd = None;
+		if let Err(e) = channel.try_send_message(quick_xml::se::to_string(&public).unwrap()) {
+			warn!(
+				"Failed to send the game state to spectator {}: {}",
+				user_id, e
+			);
+		}
+		game.spectators.insert(user_id, channel);
+		game.watchers.insert(user_id);
+	}
+
+	pub(crate) async fn remove_spectator(&self, user_id: i32) -> bool {
+		self.write().await.spectators.remove(&user_id).is_some()
+	}
+
+	/// Records a public message in the replay and sends it to every spectator, it must not
+	/// contain a `CMD`. Spectators never block the game, if they are behind the message is dropped
+	pub(crate) async fn broadcast_public(&self, xml: &str) {
+		let game = self.read().await;
+		game.replay.record_state(xml);
+		let spectators = game.spectators.clone();
 		drop(game);
-		channel.unwrap().xml_channel.send_message(msg).await
+		for (user_id, channel) in spectators {
+			match channel.try_send_message(xml.to_string()) {
+				Ok(()) => {}
+				Err(flume::TrySendError::Full(_)) => {
+					warn!("Spectator {} is behind, message dropped", user_id);
+				}
+				Err(flume::TrySendError::Disconnected(_)) => {
+					self.remove_spectator(user_id).await;
+				}
+			}
+		}
+	}
+
+	/// Tells the players and the spectators that the game ended without an end screen
+	pub(crate) async fn send_error_to_all(&self) {
+		let game = self.read().await;
+		let mut channels: Vec<ListenPlayerChannel> = game.spectators.values().cloned().collect();
+		for player in game.utils.players() {
+			if let Some(player_channels) = game
+				.utils
+				.get_player(&player)
+				.and_then(|info| info.get_player_channels().as_ref())
+			{
+				channels.push(player_channels.xml_channel.clone());
+			}
+		}
+		drop(game);
+		let error = quick_xml::se::to_string(&ChannelErrorResponse::new()).unwrap();
+		for channel in channels {
+			// nobody is waited for after the game is over
+			let _ = channel.try_send_message(error.clone());
+		}
+	}
+
+	/// Hands the seat of a disconnected player to a bot and lets the others know about it
+	pub(crate) async fn player_disconnected(&self, player: &PlayerName) {
+		let mut game = self.write().await;
+		match game.utils.get_player_mut(player) {
+			Some(info) if info.is_player() => info.disconnect(),
+			_ => return,
+		}
+		game.state.players_connected = game.utils.connected_players();
+		warn!("Player {:?} disconnected, a bot plays for them", player);
 	}
 
 	pub(crate) async fn recv_command_channel(
 		&self,
 		player: &PlayerName,
-	) -> Result<ServerCommand, flume::RecvError> {
+	) -> anyhow::Result<ServerCommand> {
 		let read_game = self.read().await;
 		let channel = read_game
 			.utils
 			.get_player(player)
-			.unwrap()
-			.get_player_channels()
-			.clone();
+			.and_then(|info| info.get_player_channels().clone());
 		drop(read_game);
-		channel.unwrap().command_channel.recv_message().await
+		let Some(channel) = channel else {
+			bail!("Channels not found for player: {:?}", player);
+		};
+		let command = channel.command_channel.recv_message().await?;
+		self.read().await.replay.record_command(*player, &command);
+		Ok(command)
+	}
+
+	/// Stores the replay of the game in the database, its players and watchers can view it
+	pub(crate) async fn save_replay(&self) -> anyhow::Result<()> {
+		let game = self.read().await;
+		let game_id = game.players.as_ref().map_or(0, |players| players.game_id);
+		let mut viewers = game
+			.players
+			.as_ref()
+			.map(PlayerInfo::user_ids)
+			.unwrap_or_default();
+		viewers.extend(&game.watchers);
+		let events = game.replay.to_json()?;
+		let seed = game.rng.seed();
+		let Some(storage) = game.storage.clone() else {
+			bail!("Game {} has no database to store the replay", game_id);
+		};
+		drop(game);
+
+		storage.save_replay(game_id, seed, &events, &viewers).await
 	}
 
+	/// Stores the progress of the human players with the rewards they earned in the game, the
+	/// players who left the game too
+	pub(crate) async fn save_profiles(&self, rewards: &[GameReward]) -> anyhow::Result<()> {
+		let game = self.read().await;
+		let Some(storage) = game.storage.clone() else {
+			return Ok(());
+		};
+		let profiles: Vec<Profile> = rewards
+			.iter()
+			.filter_map(|reward| {
+				let data = game.players.as_ref()?.get_player_data(&reward.player)?;
+				(!data.is_bot()).then(|| data.profile_after_game(reward.xp(), reward.golds))
+			})
+			.collect();
+		drop(game);
+
+		for profile in &profiles {
+			storage.save_profile(profile).await?;
+		}
+		Ok(())
+	}
+
+	/// Stores how every seat finished the game
+	pub(crate) async fn save_result(&self, rewards: &[GameReward]) -> anyhow::Result<()> {
+		let game = self.read().await;
+		let Some(storage) = game.storage.clone() else {
+			return Ok(());
+		};
+		let Some(players) = game.players.as_ref() else {
+			bail!("The game has no players");
+		};
+		let game_id = players.game_id;
+		let results: Vec<GameResult> = rewards
+			.iter()
+			.map(|reward| GameResult {
+				seat: reward.player as i16,
+				user_id: players
+					.get_player_data(&reward.player)
+					.filter(|data| !data.is_bot())
+					.map(|data| data.id),
+				placing: i16::from(reward.placing),
+				points: i32::from(reward.points),
+				forfeited: reward.forfeited,
+				xp: reward.xp() as i32,
+			})
+			.collect();
+		drop(game);
+
+		storage.save_result(game_id, &results).await
+	}
+
+	/// Receives commands from the player until the desired command arrives or the deadline
+	/// passes, other commands are dropped
 	pub(crate) async fn loop_recv_command(
 		&self,
 		player: &PlayerName,
 		desired_command: ServerCommand,
+		deadline: Duration,
 	) -> anyhow::Result<ServerCommand> {
+		self.loop_recv_command_with(player, desired_command, deadline, |_| async { Ok(()) })
+			.await
+	}
+
+	/// Like `loop_recv_command`, but the other commands are handed to `on_other`, an error of it
+	/// stops the waiting
+	pub(crate) async fn loop_recv_command_with<F>(
+		&self,
+		player: &PlayerName,
+		desired_command: ServerCommand,
+		deadline: Duration,
+		mut on_other: impl FnMut(ServerCommand) -> F,
+	) -> anyhow::Result<ServerCommand>
+	where
+		F: Future<Output = anyhow::Result<()>>,
+	{
 		trace!(
 			"loop_recv_command for player: {:?}, desired_command: {:?}",
 			player, desired_command
 		);
-		let timeout_duration = tokio::time::Duration::from_secs(15);
 
-		match tokio::time::timeout(timeout_duration, async {
+		match tokio::time::timeout(deadline, async {
 			let mut command = self.recv_command_channel(player).await?;
 			while !command.variant_eq(&desired_command) {
 				trace!(
 					"Waiting for command {:?} for player: {:?}, received: {:?}",
 					desired_command, player, command
 				);
+				on_other(command).await?;
 				command = self.recv_command_channel(player).await?;
 			}
 			Ok(command)
 		})
 		.await
 		{
-			Ok(Ok(command)) => Ok(command), // Inner future succeeded and returned Ok(command)
-			Ok(Err(e)) => Err(e), // Inner future succeeded but returned an Err(e) from recv_command_channel
+			Ok(result) => result,
 			Err(_) => Err(anyhow::anyhow!(
-				"Timeout (10s) waiting for command {:?} for player: {:?}",
+				"Timeout ({}s) waiting for command {:?} for player: {:?}",
+				deadline.as_secs(),
 				desired_command,
 				player
 			)),
@@ -157,6 +448,11 @@ impl SharedTrivGame {
 			send_player_commongame(self.arc_clone().borrow(), player).await;
 			trace!("send_to_all_active: {:?}", player);
 		}
+
+		let mut public = self.read().await.clone();
+		public.cmd = None;
+		self.broadcast_public(&quick_xml::se::to_string(&public).unwrap())
+			.await;
 	}
 }
 
@@ -173,15 +469,37 @@ pub struct TriviadorGame {
 	#[serde(skip)]
 	pub(crate) utils: GamePlayerInfo,
 	#[serde(skip)]
-	pub(crate) db: PgPool,
+	pub(crate) rules: GameRules,
+	/// Listen channels of the spectators by their user id
+	#[serde(skip)]
+	pub(crate) spectators: HashMap<i32, ListenPlayerChannel>,
+	/// Every user who watched the game, they can view its replay
+	#[serde(skip)]
+	pub(crate) watchers: HashSet<i32>,
+	#[serde(skip)]
+	pub(crate) replay: ReplayLog,
+	/// Replays are only stored when there is a database
+	#[serde(skip)]
+	pub(crate) storage: Option<SharedStorage>,
+	#[serde(skip)]
+	pub(crate) questions: SharedQuestionProvider,
+	#[serde(skip)]
+	pub(crate) rng: GameRng,
 }
 
 impl TriviadorGame {
 	/// Creates a new triviador game
-	pub(crate) fn new_game(player_info: PlayerInfo, db: PgPool) -> TriviadorGame {
+	pub(crate) fn new_game(
+		player_info: PlayerInfo,
+		storage: Option<SharedStorage>,
+		questions: SharedQuestionProvider,
+		map: Arc<GameMap>,
+		rng: GameRng,
+	) -> TriviadorGame {
+		info!("Game {} uses seed {}", player_info.game_id, rng.seed());
 		TriviadorGame {
 			state: TriviadorState {
-				map_name: "MAP_WD".to_string(),
+				map_name: map.name().to_string(),
 				game_state: GameState::loading_screen(),
 				round_info: RoundInfo {
 					mini_phase_num: 0,
@@ -193,20 +511,115 @@ impl TriviadorGame {
 				players_points: PlayerPoints::new(),
 				selection: Selection::new(),
 				base_info: Bases::all_available(),
-				areas_info: Areas::new(),
+				areas_info: Areas::new(map),
 				available_areas: AvailableAreas::new(),
-				used_helps: "0".to_string(),
+				used_helps: UsedHelps::new(),
 				fill_round_winners: FillRound::new(),
 				room_type: None,
 				shield_mission: None,
 				war_order: None,
 				active_player: None,
 				eliminated_players: vec![],
+				forfeited_players: vec![],
+				bot_seats: vec![],
+				tiebreak_ranking: vec![],
+				missions: HashMap::new(),
 			},
 			players: Some(player_info),
 			cmd: None,
 			utils: GamePlayerInfo::new(),
-			db,
+			rules: config().game.rules(),
+			spectators: HashMap::new(),
+			watchers: HashSet::new(),
+			replay: ReplayLog::new(),
+			storage,
+			questions,
+			rng,
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+
+	use super::*;
+	use crate::bot::strategy::BotDifficulty;
+	use crate::game_handlers::s_game::SGamePlayerInfo;
+	use crate::triviador::game_player_data::GamePlayerData;
+	use crate::triviador::question_provider::InMemoryQuestions;
+
+	fn test_game(forfeit_policy: ForfeitPolicy) -> SharedTrivGame {
+		let mut rng = StdRng::seed_from_u64(1);
+		let info = PlayerInfo {
+			p1_name: "Lajos".to_string(),
+			p2_name: "Bot".to_string(),
+			p3_name: "Bot".to_string(),
+			pd1: GamePlayerData::new_player(5, &mut rng),
+			pd2: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
+			pd3: GamePlayerData::new_bot(BotDifficulty::default(), &mut rng),
+			you: "1,2,3".to_string(),
+			game_id: 1,
+			room: "1".to_string(),
+			rules: "0,0".to_string(),
+		};
+		let questions = Arc::new(InMemoryQuestions::generated(1, &mut rng));
+		let mut game =
+			TriviadorGame::new_game(info, None, questions, GameMap::hungary(), GameRng::new(1));
+		game.rules.forfeit_policy = forfeit_policy;
+		game.utils
+			.add(PlayerName::Player1, SGamePlayerInfo::new(true));
+		SharedTrivGame::new(game)
+	}
+
+	#[tokio::test]
+	async fn forfeited_seat_is_played_by_a_bot() {
+		let game = test_game(ForfeitPolicy::KeepWithBot);
+		assert_eq!(game.forfeit_player(5).await.unwrap(), PlayerName::Player1);
+		let state = game.read().await;
+		assert!(
+			state
+				.state
+				.eliminated_players
+				.contains(&PlayerName::Player1)
+		);
+		assert!(
+			state
+				.state
+				.remaining_players()
+				.contains(&PlayerName::Player1)
+		);
+		assert!(
+			!state
+				.utils
+				.get_player(&PlayerName::Player1)
+				.unwrap()
+				.is_player()
+		);
+		drop(state);
+		assert!(game.forfeit_player(5).await.is_err());
+		// the bot loses the castle of the seat
+		let mut state = game.write().await;
+		state.state.eliminate(PlayerName::Player1);
+		assert!(!state.state.is_in_play(&PlayerName::Player1));
+	}
+
+	#[tokio::test]
+	async fn forfeited_seat_is_eliminated_when_left_neutral() {
+		let game = test_game(ForfeitPolicy::LeaveNeutral);
+		game.forfeit_player(5).await.unwrap();
+		let state = game.read().await;
+		assert!(
+			state
+				.state
+				.eliminated_players
+				.contains(&PlayerName::Player1)
+		);
+		assert!(
+			!state
+				.state
+				.remaining_players()
+				.contains(&PlayerName::Player1)
+		);
+	}
+}
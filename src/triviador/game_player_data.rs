@@ -5,8 +5,11 @@ use anyhow::bail;
 use rand::prelude::StdRng;
 use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize, Serializer};
+use tracing::warn;
 
+use crate::bot::strategy::{BotDifficulty, BotStrategy};
 use crate::emulator::Emulator;
+use crate::storage::{Profile, SharedStorage};
 
 #[derive(Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
 #[repr(u8)]
@@ -70,11 +73,17 @@ pub struct GamePlayerData {
 	pub custom_avatar: bool,
 	pub soldier: i16,
 	pub act_league: i16,
+	/// Only set for robots, it is not sent to the client
+	pub bot_difficulty: Option<BotDifficulty>,
+	/// Loaded from the profile and spent on tip helps, it is not sent to the client
+	pub golds: i32,
 }
 
 impl GamePlayerData {
-	pub(crate) fn new_player(id: i32) -> GamePlayerData {
-		let mut rng = StdRng::from_entropy();
+	/// Golds of a player without a profile, the same as the village shows
+	const START_GOLDS: i32 = 3000;
+
+	pub(crate) fn new_player(id: i32, rng: &mut impl Rng) -> GamePlayerData {
 		// there could be more types
 		let soldier = rng.gen_range(1..8);
 
@@ -89,11 +98,50 @@ impl GamePlayerData {
 			custom_avatar: false,
 			soldier,
 			act_league: 1,
+			bot_difficulty: None,
+			golds: Self::START_GOLDS,
 		}
 	}
 
-	pub fn new_bot() -> GamePlayerData {
-		let mut rng = StdRng::from_entropy();
+	/// A player with the progress stored in the profile, the defaults if there is none
+	pub(crate) async fn load_player(
+		storage: &SharedStorage,
+		id: i32,
+		rng: &mut (impl Rng + Send),
+	) -> GamePlayerData {
+		let mut data = GamePlayerData::new_player(id, rng);
+		match storage.profile(id).await {
+			Ok(Some(profile)) => data.apply_profile(&profile),
+			Ok(None) => {}
+			Err(e) => warn!("Failed to load the profile of {}: {}", id, e),
+		}
+		data
+	}
+
+	fn apply_profile(&mut self, profile: &Profile) {
+		self.xp_points = profile.xp_points;
+		self.xp_level = profile.xp_level;
+		self.game_count = profile.game_count;
+		self.country_id = profile.country_id.clone();
+		self.castle_level = profile.castle_level;
+		self.golds = profile.golds;
+	}
+
+	/// The profile to store after a game which earned the player `xp` and `golds`, the golds
+	/// spent in the game are taken from the profile too
+	pub(crate) fn profile_after_game(&self, xp: u32, golds: u32) -> Profile {
+		Profile {
+			user_id: self.id,
+			xp_points: self.xp_points + xp as i32,
+			xp_level: self.xp_level,
+			game_count: self.game_count + 1,
+			country_id: self.country_id.clone(),
+			castle_level: self.castle_level,
+			golds: self.golds + golds as i32,
+		}
+	}
+
+	pub fn new_bot(difficulty: BotDifficulty, rng: &mut impl Rng) -> GamePlayerData {
 		// there could be more types
 		let soldier = rng.gen_range(1..8);
 
@@ -108,12 +156,29 @@ impl GamePlayerData {
 			custom_avatar: false,
 			soldier,
 			act_league: 1,
+			bot_difficulty: Some(difficulty),
+			golds: Self::START_GOLDS,
 		}
 	}
 
+	/// Takes the price from the golds of the player, false if they can't afford it
+	pub(crate) fn spend_golds(&mut self, price: u16) -> bool {
+		let price = i32::from(price);
+		if self.golds < price {
+			return false;
+		}
+		self.golds -= price;
+		true
+	}
+
 	pub(crate) fn is_bot(&self) -> bool {
 		self.id == -1
 	}
+
+	/// The strategy of the robot, humans get the default one used after they disconnect
+	pub(crate) fn bot_strategy(&self) -> BotStrategy {
+		BotStrategy::new(self.bot_difficulty.unwrap_or_default())
+	}
 }
 
 // Emulates a Player
@@ -136,6 +201,8 @@ impl Emulator for GamePlayerData {
 			custom_avatar: false,
 			soldier,
 			act_league: 1,
+			bot_difficulty: None,
+			golds: Self::START_GOLDS,
 		}
 	}
 }
@@ -176,6 +243,8 @@ impl FromStr for GamePlayerData {
 			custom_avatar,
 			soldier,
 			act_league,
+			bot_difficulty: None,
+			golds: Self::START_GOLDS,
 		})
 	}
 }
@@ -213,3 +282,26 @@ impl Serialize for GamePlayerData {
 		serializer.serialize_str(self.to_string().as_str())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage;
+
+	#[tokio::test]
+	async fn golds_are_kept_between_games() {
+		let storage = storage::connect("sqlite::memory:").await.unwrap();
+		let mut rng = StdRng::seed_from_u64(1);
+		let mut data = GamePlayerData::load_player(&storage, 5, &mut rng).await;
+		assert_eq!(data.golds, GamePlayerData::START_GOLDS);
+		assert!(data.spend_golds(2000));
+		assert!(!data.spend_golds(2000));
+		storage
+			.save_profile(&data.profile_after_game(0, 300))
+			.await
+			.unwrap();
+
+		let data = GamePlayerData::load_player(&storage, 5, &mut rng).await;
+		assert_eq!(data.golds, 1300);
+	}
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use super::game_player_data::PlayerName;
+use crate::config::config;
+
+/// The random source of a game, every random decision is drawn from it so a game can be played
+/// again from its seed. Clones share the same sequence
+#[derive(Clone, Debug)]
+pub(crate) struct GameRng {
+	seed: u64,
+	rng: Arc<Mutex<StdRng>>,
+}
+
+impl GameRng {
+	pub(crate) fn new(seed: u64) -> GameRng {
+		GameRng {
+			seed,
+			rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+		}
+	}
+
+	/// Uses the configured seed if it's set, a random seed otherwise
+	pub(crate) fn from_config() -> GameRng {
+		GameRng::new(config().game.seed.unwrap_or_else(rand::random))
+	}
+
+	pub(crate) fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// A generator for a single decision, seeded by the next value of the game's sequence
+	pub(crate) fn fork(&self) -> StdRng {
+		let seed = match self.rng.lock() {
+			Ok(mut rng) => rng.next_u64(),
+			Err(poisoned) => poisoned.into_inner().next_u64(),
+		};
+		StdRng::seed_from_u64(seed)
+	}
+
+	/// A generator for every player in seat order, for decisions made concurrently
+	pub(crate) fn fork_each(&self, players: &[PlayerName]) -> HashMap<PlayerName, StdRng> {
+		let mut players = players.to_vec();
+		players.sort_by_key(|player| *player as u8);
+		players
+			.into_iter()
+			.map(|player| (player, self.fork()))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::*;
+
+	#[test]
+	fn same_seed_same_game() {
+		let draw =
+			|rng: &GameRng| -> Vec<u32> { (0..5).map(|_| rng.fork().gen_range(0..1000)).collect() };
+		let first = GameRng::new(1234);
+		let second = GameRng::new(1234);
+		assert_eq!(draw(&first), draw(&second));
+		assert_eq!(first.seed(), 1234);
+
+		let players = [PlayerName::Player3, PlayerName::Player1];
+		let mut first = first.fork_each(&players);
+		let mut second = second.fork_each(&[PlayerName::Player1, PlayerName::Player3]);
+		for player in players {
+			assert_eq!(
+				first.get_mut(&player).unwrap().next_u64(),
+				second.get_mut(&player).unwrap().next_u64()
+			);
+		}
+	}
+}
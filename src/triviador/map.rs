@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{Context, bail, ensure};
+use serde::Deserialize;
+use tracing::info;
+
+use super::county::County;
+
+static HUNGARY: LazyLock<Arc<GameMap>> = LazyLock::new(|| {
+	Arc::new(
+		GameMap::from_json(include_str!("../../maps/hungary.json")).expect("Invalid Hungarian map"),
+	)
+});
+
+/// An area as defined in a map file
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct MapArea {
+	pub id: u8,
+	pub name: String,
+	/// Flag code sent in the countries response
+	pub flag: String,
+	#[serde(default)]
+	pub description: String,
+	pub neighbours: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct MapFile {
+	name: String,
+	areas: Vec<MapArea>,
+}
+
+/// The areas of a map and which of them are next to each other
+#[derive(Debug, PartialEq)]
+pub(crate) struct GameMap {
+	/// Sent to the client as the map of the game
+	name: String,
+	/// Ordered by id, this is the order areas are encoded in
+	counties: Vec<County>,
+	areas: Vec<MapArea>,
+	neighbours: HashMap<County, HashSet<County>>,
+}
+
+impl GameMap {
+	/// Builds a map from its areas, the ids have to be continuous from 1, every neighbour has to
+	/// be mutual and every area has to be reachable
+	pub(crate) fn new(name: &str, mut areas: Vec<MapArea>) -> anyhow::Result<GameMap> {
+		areas.sort_by_key(|area| area.id);
+		ensure!(!areas.is_empty(), "Map {} has no areas", name);
+		let mut counties = Vec::with_capacity(areas.len());
+		for (i, area) in areas.iter().enumerate() {
+			if area.id as usize != i + 1 {
+				bail!("Area ids of map {} must be continuous from 1", name);
+			}
+			let county = County::try_from(area.id)
+				.with_context(|| format!("Map {} has too many areas", name))?;
+			counties.push(county);
+		}
+		let mut neighbours: HashMap<County, HashSet<County>> = HashMap::new();
+		for area in &areas {
+			let mut area_neighbours = HashSet::with_capacity(area.neighbours.len());
+			for id in &area.neighbours {
+				let neighbour = County::try_from(*id)?;
+				ensure!(
+					counties.contains(&neighbour),
+					"Area {} of map {} has an unknown neighbour {}",
+					area.id,
+					name,
+					id
+				);
+				area_neighbours.insert(neighbour);
+			}
+			neighbours.insert(County::try_from(area.id)?, area_neighbours);
+		}
+		for (county, county_neighbours) in &neighbours {
+			for neighbour in county_neighbours {
+				let mutual = neighbours
+					.get(neighbour)
+					.is_some_and(|other| other.contains(county));
+				ensure!(
+					mutual,
+					"Area {} and {} of map {} are not mutual neighbours",
+					county,
+					neighbour,
+					name
+				);
+			}
+		}
+		let map = GameMap {
+			name: name.to_string(),
+			counties,
+			areas,
+			neighbours,
+		};
+		ensure!(map.is_connected(), "Map {} is not connected", name);
+		Ok(map)
+	}
+
+	pub(crate) fn from_json(json: &str) -> anyhow::Result<GameMap> {
+		let file: MapFile = serde_json::from_str(json)?;
+		GameMap::new(&file.name, file.areas)
+	}
+
+	/// The Hungarian counties, the default map
+	pub(crate) fn hungary() -> Arc<GameMap> {
+		Arc::clone(&HUNGARY)
+	}
+
+	pub(crate) fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub(crate) fn counties(&self) -> &[County] {
+		&self.counties
+	}
+
+	pub(crate) fn areas(&self) -> &[MapArea] {
+		&self.areas
+	}
+
+	pub(crate) fn contains(&self, county: &County) -> bool {
+		self.neighbours.contains_key(county)
+	}
+
+	pub(crate) fn is_neighbour(&self, county: &County, other: &County) -> bool {
+		self.neighbours
+			.get(county)
+			.is_some_and(|neighbours| neighbours.contains(other))
+	}
+
+	pub(crate) fn neighbours(&self, county: &County) -> impl Iterator<Item = &County> {
+		self.neighbours.get(county).into_iter().flatten()
+	}
+
+	fn is_connected(&self) -> bool {
+		let Some(first) = self.counties.first() else {
+			return false;
+		};
+		let mut visited = HashSet::from([*first]);
+		let mut queue = vec![*first];
+		while let Some(county) = queue.pop() {
+			for neighbour in &self.neighbours[&county] {
+				if visited.insert(*neighbour) {
+					queue.push(*neighbour);
+				}
+			}
+		}
+		visited.len() == self.counties.len()
+	}
+}
+
+/// Every map the server knows by name, loaded at startup
+#[derive(Clone, Debug)]
+pub(crate) struct MapRegistry(Arc<HashMap<String, Arc<GameMap>>>);
+
+impl MapRegistry {
+	/// Loads every `.json` map of the directory, the Hungarian map is always available
+	pub(crate) fn load_dir(dir: impl AsRef<Path>) -> anyhow::Result<MapRegistry> {
+		let mut maps = HashMap::new();
+		let hungary = GameMap::hungary();
+		maps.insert(hungary.name().to_string(), hungary);
+		let dir = dir.as_ref();
+		if dir.is_dir() {
+			for entry in std::fs::read_dir(dir)? {
+				let path = entry?.path();
+				if path.extension().is_none_or(|ext| ext != "json") {
+					continue;
+				}
+				let json = std::fs::read_to_string(&path)?;
+				let map = GameMap::from_json(&json)
+					.with_context(|| format!("Invalid map file {}", path.display()))?;
+				info!("Loaded map {} from {}", map.name(), path.display());
+				maps.insert(map.name().to_string(), Arc::new(map));
+			}
+		}
+		Ok(MapRegistry(Arc::new(maps)))
+	}
+
+	pub(crate) fn get(&self, name: &str) -> Option<Arc<GameMap>> {
+		self.0.get(name).cloned()
+	}
+
+	/// The map by name or the default map if there is no such map
+	pub(crate) fn get_or_default(&self, name: Option<&str>) -> Arc<GameMap> {
+		name.and_then(|name| self.get(name))
+			.unwrap_or_else(GameMap::hungary)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::triviador::county::hungary;
+
+	fn area(id: u8, neighbours: Vec<u8>) -> MapArea {
+		MapArea {
+			id,
+			name: id.to_string(),
+			flag: "--".to_string(),
+			description: String::new(),
+			neighbours,
+		}
+	}
+
+	#[test]
+	fn hungary_is_valid() {
+		let map = GameMap::hungary();
+		assert_eq!(map.counties().len(), 19);
+		assert!(map.is_neighbour(&hungary::PEST, &hungary::NOGRAD));
+		assert!(!map.is_neighbour(&hungary::PEST, &hungary::VAS));
+		assert!(map.is_neighbour(&hungary::GYOR_MOSON_SOPRON, &hungary::VAS));
+		assert!(County::try_from(0).is_err());
+	}
+
+	#[test]
+	fn rejects_invalid_maps() {
+		let one_sided = GameMap::new("TEST", vec![area(1, vec![2]), area(2, vec![])]);
+		assert!(one_sided.is_err());
+
+		let gap = GameMap::new("TEST", vec![area(2, vec![])]);
+		assert!(gap.is_err());
+
+		let disconnected = GameMap::new(
+			"TEST",
+			vec![area(1, vec![2]), area(2, vec![1]), area(3, vec![])],
+		);
+		assert!(disconnected.is_err());
+
+		let too_big = GameMap::new("TEST", (1..=31).map(|id| area(id, vec![])).collect());
+		assert!(too_big.is_err());
+	}
+
+	#[test]
+	fn registry_falls_back_to_hungary() {
+		let registry = MapRegistry::load_dir("maps").unwrap();
+		assert_eq!(registry.get_or_default(Some("MAP_WD")).name(), "MAP_WD");
+		assert_eq!(registry.get_or_default(Some("MISSING")).name(), "MAP_WD");
+	}
+}
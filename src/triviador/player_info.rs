@@ -1,6 +1,6 @@
 use serde::Serialize;
 
-use crate::triviador::game_player_data::GamePlayerData;
+use crate::triviador::game_player_data::{GamePlayerData, PlayerName};
 
 #[derive(Serialize, Debug, Clone)]
 pub struct PlayerInfo {
@@ -25,3 +25,47 @@ pub struct PlayerInfo {
 	#[serde(rename = "@RULES")]
 	pub rules: String,
 }
+
+impl PlayerInfo {
+	/// Returns the seat of the human player with the given user id
+	pub(crate) fn get_player_by_id(&self, user_id: i32) -> Option<PlayerName> {
+		[
+			(PlayerName::Player1, &self.pd1),
+			(PlayerName::Player2, &self.pd2),
+			(PlayerName::Player3, &self.pd3),
+		]
+		.into_iter()
+		.find(|(_, data)| !data.is_bot() && data.id == user_id)
+		.map(|(player, _)| player)
+	}
+
+	pub(crate) fn get_player_data(&self, player: &PlayerName) -> Option<&GamePlayerData> {
+		match player {
+			PlayerName::Player1 => Some(&self.pd1),
+			PlayerName::Player2 => Some(&self.pd2),
+			PlayerName::Player3 => Some(&self.pd3),
+			PlayerName::Nobody => None,
+		}
+	}
+
+	pub(crate) fn get_player_data_mut(
+		&mut self,
+		player: &PlayerName,
+	) -> Option<&mut GamePlayerData> {
+		match player {
+			PlayerName::Player1 => Some(&mut self.pd1),
+			PlayerName::Player2 => Some(&mut self.pd2),
+			PlayerName::Player3 => Some(&mut self.pd3),
+			PlayerName::Nobody => None,
+		}
+	}
+
+	/// User ids of the human players
+	pub(crate) fn user_ids(&self) -> Vec<i32> {
+		[&self.pd1, &self.pd2, &self.pd3]
+			.into_iter()
+			.filter(|data| !data.is_bot())
+			.map(|data| data.id)
+			.collect()
+	}
+}
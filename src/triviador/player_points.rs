@@ -21,6 +21,10 @@ impl PlayerPoints {
 		*old_points = points;
 	}
 
+	pub(crate) fn get_player_points(&self, name: &PlayerName) -> i16 {
+		self.0.get(name).copied().unwrap_or(0)
+	}
+
 	pub(crate) fn change_player_points(&mut self, name: &PlayerName, by: i16) {
 		let old_points = self.0.get_mut(name).unwrap();
 		*old_points += by;
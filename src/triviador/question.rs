@@ -1,13 +1,12 @@
-use std::collections::HashMap;
-
+use rand::Rng;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_with::skip_serializing_none;
-use sqlx::PgPool;
 use tracing::error;
 
 use super::game_player_data::PlayerName;
 use crate::emulator::Emulator;
 use crate::triviador::cmd::Cmd;
+use crate::triviador::tip_help::TipHelp;
 use crate::triviador::triviador_state::TriviadorState;
 
 #[skip_serializing_none]
@@ -29,7 +28,7 @@ impl QuestionStageResponse {
 		QuestionStageResponse {
 			state,
 			question: Some(question),
-			cmd: Some(Cmd::answer_command(20)),
+			cmd: Some(Cmd::answer_command(Cmd::answer_timeout())),
 			answer_result: None,
 		}
 	}
@@ -114,29 +113,16 @@ impl Question {
 		}
 	}
 
-	pub(crate) async fn get_from_db(pool: &PgPool) -> Question {
-		let rec = sqlx::query!(
-			r#"SELECT question,
-		    answer1,
-			answer2,
-			answer3,
-			answer4,
-			theme,
-			good
-			FROM choice_questions ORDER BY RANDOM() LIMIT 1"#,
-		)
-		.fetch_one(pool)
-		.await
-		.unwrap();
-
+	/// A placeholder question with a random correct answer, used by in-memory questions
+	pub(crate) fn generated(rng: &mut impl Rng) -> Question {
 		Question::new(
-			rec.question,
-			rec.answer1,
-			rec.answer2,
-			rec.answer3,
-			rec.answer4,
-			rec.theme,
-			Some(rec.good as u8),
+			"Generated question".to_string(),
+			"1".to_string(),
+			"2".to_string(),
+			"3".to_string(),
+			"4".to_string(),
+			"1".to_string(),
+			Some(rng.gen_range(1..=4)),
 		)
 	}
 }
@@ -238,31 +224,22 @@ impl TipStageResponse {
 	) -> TipStageResponse {
 		TipStageResponse {
 			state,
-			cmd: Some(Cmd::tip_command(15)),
+			cmd: Some(Cmd::tip_command(Cmd::tip_timeout())),
 			tip_question: Some(tip_question),
 			tip_info: None,
 			tip_result: None,
 		}
 	}
 
+	/// Evaluates the tips of the `participants`, players who didn't tip are placed last
 	pub(crate) fn new_tip_result(
 		state: TriviadorState,
-		tip_info: TipInfo,
+		mut tip_info: TipInfo,
 		good: i32,
+		participants: &[PlayerName],
 	) -> TipStageResponse {
-		let mut results: HashMap<PlayerName, i32> = HashMap::new();
-		if let Some(tip) = tip_info.player_1_tip {
-			results.insert(PlayerName::Player1, TipInfo::difference(good, tip));
-		}
-		if let Some(tip) = tip_info.player_2_tip {
-			results.insert(PlayerName::Player2, TipInfo::difference(good, tip));
-		}
-		if let Some(tip) = tip_info.player_3_tip {
-			results.insert(PlayerName::Player3, TipInfo::difference(good, tip));
-		}
-
-		let mut sorted_results: Vec<_> = results.iter().collect();
-		sorted_results.sort_by(|a, b| a.1.cmp(b.1));
+		tip_info.set_closeness(good);
+		let ranking = tip_info.ranking(good, participants);
 
 		TipStageResponse {
 			state,
@@ -270,8 +247,8 @@ impl TipStageResponse {
 			tip_question: None,
 			tip_info: Some(tip_info),
 			tip_result: Some(TipResult {
-				winner: *sorted_results[0].0,
-				second: *sorted_results[1].0,
+				winner: ranking.first().copied().unwrap_or(PlayerName::Nobody),
+				second: ranking.get(1).copied().unwrap_or(PlayerName::Nobody),
 				good,
 			}),
 		}
@@ -332,31 +309,83 @@ impl TipInfo {
 
 	pub(crate) fn add_player_tip(&mut self, player: PlayerName, tip: i32, time: f32) {
 		self.timeorder.push(player);
-		// todo implement closeness calculation
 		match player {
 			PlayerName::Player1 => {
 				self.player_1_tip = Some(tip);
 				self.player_1_time = Some(time);
-				self.player_1_closeness = Some("10".to_string());
 			}
 			PlayerName::Player2 => {
 				self.player_2_tip = Some(tip);
 				self.player_2_time = Some(time);
-				self.player_2_closeness = Some("90".to_string());
 			}
 			PlayerName::Player3 => {
 				self.player_3_tip = Some(tip);
 				self.player_3_time = Some(time);
-				self.player_3_closeness = Some("90".to_string());
 			}
 			_ => {
 				error!("Unable to set player tip, invalid player id: {}", player);
 			}
 		}
 	}
+	pub(crate) fn get_player_tip(&self, player: &PlayerName) -> Option<i32> {
+		match player {
+			PlayerName::Player1 => self.player_1_tip,
+			PlayerName::Player2 => self.player_2_tip,
+			PlayerName::Player3 => self.player_3_tip,
+			PlayerName::Nobody => None,
+		}
+	}
+
+	pub(crate) fn get_player_time(&self, player: &PlayerName) -> Option<f32> {
+		match player {
+			PlayerName::Player1 => self.player_1_time,
+			PlayerName::Player2 => self.player_2_time,
+			PlayerName::Player3 => self.player_3_time,
+			PlayerName::Nobody => None,
+		}
+	}
+
 	pub(crate) fn difference(good: i32, answer: i32) -> i32 {
 		(good - answer).abs()
 	}
+
+	/// How close the answer is to the good value in percent, only the exact answer is 100
+	pub(crate) fn closeness(good: i32, answer: i32) -> u8 {
+		if good == answer {
+			return 100;
+		}
+		let difference = Self::difference(good, answer) as i64;
+		let percent = 100 - difference * 100 / (good as i64).abs().max(1);
+		percent.clamp(1, 99) as u8
+	}
+
+	fn set_closeness(&mut self, good: i32) {
+		let closeness = |tip: Option<i32>| tip.map(|tip| Self::closeness(good, tip).to_string());
+		self.player_1_closeness = closeness(self.player_1_tip);
+		self.player_2_closeness = closeness(self.player_2_tip);
+		self.player_3_closeness = closeness(self.player_3_tip);
+	}
+
+	/// Orders the participants by the difference of their tip, equal differences are decided by
+	/// the answer time, then by the order of arrival. Participants without a tip come last.
+	pub(crate) fn ranking(&self, good: i32, participants: &[PlayerName]) -> Vec<PlayerName> {
+		let (mut tipped, missing): (Vec<PlayerName>, Vec<PlayerName>) = participants
+			.iter()
+			.partition(|player| self.get_player_tip(player).is_some());
+
+		let arrival = |player: &PlayerName| self.timeorder.iter().position(|p| p == player);
+		tipped.sort_by(|a, b| {
+			let difference = |player| Self::difference(good, self.get_player_tip(player).unwrap());
+			let time = |player| self.get_player_time(player).unwrap_or(f32::MAX);
+			difference(a)
+				.cmp(&difference(b))
+				.then(time(a).total_cmp(&time(b)))
+				.then(arrival(a).cmp(&arrival(b)))
+		});
+
+		tipped.extend(missing);
+		tipped
+	}
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -385,25 +414,18 @@ impl TipQuestion {
 			theme,
 			icon_url: None,
 			color_code: None,
-			// todo
-			help: "{}".to_string(),
+			help: TipHelp::help_description(),
 			good,
 		}
 	}
 
-	pub(crate) async fn get_from_db(pool: &PgPool) -> TipQuestion {
-		let rec = sqlx::query!(
-			r#"SELECT
-			question,
-			theme,
-			good
-			FROM tip_questions ORDER BY RANDOM() LIMIT 1"#,
+	/// A placeholder tip question with a random correct value, used by in-memory questions
+	pub(crate) fn generated(rng: &mut impl Rng) -> TipQuestion {
+		TipQuestion::new(
+			"Generated tip question".to_string(),
+			"1".to_string(),
+			Some(rng.gen_range(1..=5000)),
 		)
-		.fetch_one(pool)
-		.await
-		.unwrap();
-
-		TipQuestion::new(rec.question, rec.theme, Some(rec.good))
 	}
 }
 
@@ -436,3 +458,68 @@ pub struct PlayerTipResponse {
 	#[serde(rename = "@HUMAN")]
 	pub human: bool,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn question_hides_good_answer() {
+		let question = quick_xml::se::to_string(&Question::emulate()).unwrap();
+		assert!(!question.contains("GOOD"));
+		let mut tip_question = TipQuestion::emulate();
+		tip_question.good = Some(1848);
+		let tip_question = quick_xml::se::to_string(&tip_question).unwrap();
+		assert!(!tip_question.contains("1848"));
+	}
+
+	#[test]
+	fn tip_closeness() {
+		assert_eq!(TipInfo::closeness(1848, 1848), 100);
+		assert_eq!(TipInfo::closeness(200, 190), 95);
+		assert_eq!(TipInfo::closeness(200, 199), 99);
+		assert_eq!(TipInfo::closeness(100, 5000), 1);
+		assert_eq!(TipInfo::closeness(0, 1), 1);
+		assert_eq!(TipInfo::closeness(-40, -30), 75);
+	}
+
+	#[test]
+	fn tip_ranking_tie_break() {
+		let mut tip_info = TipInfo::new();
+		tip_info.add_player_tip(PlayerName::Player3, 110, 4.2);
+		tip_info.add_player_tip(PlayerName::Player1, 90, 2.5);
+		tip_info.add_player_tip(PlayerName::Player2, 150, 1.0);
+
+		let ranking = tip_info.ranking(
+			100,
+			&[
+				PlayerName::Player1,
+				PlayerName::Player2,
+				PlayerName::Player3,
+			],
+		);
+		assert_eq!(
+			ranking,
+			vec![
+				PlayerName::Player1,
+				PlayerName::Player3,
+				PlayerName::Player2
+			]
+		);
+	}
+
+	#[test]
+	fn tip_ranking_missing_tips() {
+		let mut tip_info = TipInfo::new();
+		tip_info.add_player_tip(PlayerName::Player2, 150, 1.0);
+
+		let participants = [PlayerName::Player1, PlayerName::Player2];
+		assert_eq!(
+			tip_info.ranking(100, &participants),
+			vec![PlayerName::Player2, PlayerName::Player1]
+		);
+
+		let nobody_tipped = TipInfo::new().ranking(100, &participants);
+		assert_eq!(nobody_tipped, participants.to_vec());
+	}
+}
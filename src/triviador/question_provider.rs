@@ -0,0 +1,240 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::config;
+use crate::storage::SharedStorage;
+use crate::triviador::question::{Question, TipQuestion};
+
+/// Where the questions of a game come from. The random generator of the game picks the question,
+/// so the same seed asks the same questions
+#[async_trait]
+pub(crate) trait QuestionProvider: Debug + Send + Sync {
+	async fn choice_question(&self, rng: &mut StdRng) -> anyhow::Result<Question>;
+
+	async fn tip_question(&self, rng: &mut StdRng) -> anyhow::Result<TipQuestion>;
+}
+
+pub(crate) type SharedQuestionProvider = Arc<dyn QuestionProvider>;
+
+/// Questions of the database
+#[derive(Debug)]
+pub(crate) struct StoredQuestions {
+	storage: SharedStorage,
+}
+
+impl StoredQuestions {
+	pub(crate) fn new(storage: SharedStorage) -> StoredQuestions {
+		StoredQuestions { storage }
+	}
+}
+
+/// A random offset below the number of questions
+fn random_offset(count: i64, kind: &str, rng: &mut StdRng) -> anyhow::Result<i64> {
+	if count == 0 {
+		bail!("There are no {} questions", kind);
+	}
+	Ok(rng.gen_range(0..count))
+}
+
+#[async_trait]
+impl QuestionProvider for StoredQuestions {
+	async fn choice_question(&self, rng: &mut StdRng) -> anyhow::Result<Question> {
+		let count = self.storage.choice_question_count().await?;
+		let offset = random_offset(count, "choice", rng)?;
+		self.storage.choice_question_at(offset).await
+	}
+
+	async fn tip_question(&self, rng: &mut StdRng) -> anyhow::Result<TipQuestion> {
+		let count = self.storage.tip_question_count().await?;
+		let offset = random_offset(count, "tip", rng)?;
+		self.storage.tip_question_at(offset).await
+	}
+}
+
+/// Questions kept in memory, used by tests and simulated games
+#[derive(Debug, Clone)]
+pub(crate) struct InMemoryQuestions {
+	choice: Vec<Question>,
+	tip: Vec<TipQuestion>,
+}
+
+impl InMemoryQuestions {
+	pub(crate) fn new(
+		choice: Vec<Question>,
+		tip: Vec<TipQuestion>,
+	) -> anyhow::Result<InMemoryQuestions> {
+		if choice.is_empty() || tip.is_empty() {
+			bail!("Both choice and tip questions are needed");
+		}
+		Ok(InMemoryQuestions { choice, tip })
+	}
+
+	/// Placeholder questions with random correct answers
+	pub(crate) fn generated(count: usize, rng: &mut impl Rng) -> InMemoryQuestions {
+		let count = count.max(1);
+		InMemoryQuestions {
+			choice: (0..count).map(|_| Question::generated(rng)).collect(),
+			tip: (0..count).map(|_| TipQuestion::generated(rng)).collect(),
+		}
+	}
+}
+
+#[async_trait]
+impl QuestionProvider for InMemoryQuestions {
+	async fn choice_question(&self, rng: &mut StdRng) -> anyhow::Result<Question> {
+		self.choice
+			.choose(rng)
+			.cloned()
+			.context("There are no choice questions")
+	}
+
+	async fn tip_question(&self, rng: &mut StdRng) -> anyhow::Result<TipQuestion> {
+		self.tip
+			.choose(rng)
+			.cloned()
+			.context("There are no tip questions")
+	}
+}
+
+#[derive(Deserialize)]
+struct QuestionFile {
+	choice: Vec<ChoiceEntry>,
+	tip: Vec<TipEntry>,
+}
+
+#[derive(Deserialize)]
+struct ChoiceEntry {
+	question: String,
+	answers: [String; 4],
+	/// Number of the correct answer from 1 to 4
+	good: u8,
+	theme: u8,
+}
+
+#[derive(Deserialize)]
+struct TipEntry {
+	question: String,
+	good: i32,
+	theme: u8,
+}
+
+/// Questions of a JSON file, for playing offline or on a LAN without a database
+#[derive(Debug)]
+pub(crate) struct JsonQuestions {
+	questions: InMemoryQuestions,
+}
+
+impl JsonQuestions {
+	pub(crate) fn load(path: impl AsRef<Path>) -> anyhow::Result<JsonQuestions> {
+		let path = path.as_ref();
+		let json = std::fs::read_to_string(path)
+			.with_context(|| format!("Failed to read question file {}", path.display()))?;
+		let questions = Self::from_json(&json)
+			.with_context(|| format!("Invalid question file {}", path.display()))?;
+		info!(
+			"Loaded {} choice and {} tip questions from {}",
+			questions.choice.len(),
+			questions.tip.len(),
+			path.display()
+		);
+		Ok(JsonQuestions { questions })
+	}
+
+	fn from_json(json: &str) -> anyhow::Result<InMemoryQuestions> {
+		let file: QuestionFile = serde_json::from_str(json)?;
+		let mut choice = Vec::with_capacity(file.choice.len());
+		for entry in file.choice {
+			if !(1..=4).contains(&entry.good) {
+				bail!(
+					"Invalid correct answer {} of {}",
+					entry.good,
+					entry.question
+				);
+			}
+			let [op1, op2, op3, op4] = entry.answers;
+			choice.push(Question::new(
+				entry.question,
+				op1,
+				op2,
+				op3,
+				op4,
+				entry.theme.to_string(),
+				Some(entry.good),
+			));
+		}
+		let tip = file
+			.tip
+			.into_iter()
+			.map(|entry| {
+				TipQuestion::new(entry.question, entry.theme.to_string(), Some(entry.good))
+			})
+			.collect();
+		InMemoryQuestions::new(choice, tip)
+	}
+}
+
+#[async_trait]
+impl QuestionProvider for JsonQuestions {
+	async fn choice_question(&self, rng: &mut StdRng) -> anyhow::Result<Question> {
+		self.questions.choice_question(rng).await
+	}
+
+	async fn tip_question(&self, rng: &mut StdRng) -> anyhow::Result<TipQuestion> {
+		self.questions.tip_question(rng).await
+	}
+}
+
+/// Questions of the configured question file, the database otherwise
+pub(crate) fn from_config(storage: &SharedStorage) -> anyhow::Result<SharedQuestionProvider> {
+	Ok(match &config().server.questions_file {
+		Some(path) => Arc::new(JsonQuestions::load(path)?),
+		None => Arc::new(StoredQuestions::new(storage.clone())),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn example_file_is_valid() {
+		let questions = JsonQuestions::from_json(include_str!("../../questions.example.json"))
+			.expect("example questions should load");
+		let mut rng = StdRng::seed_from_u64(42);
+		let question = questions.choice_question(&mut rng).await.unwrap();
+		assert!(question.good.is_some_and(|good| (1..=4).contains(&good)));
+		assert!(
+			questions
+				.tip_question(&mut rng)
+				.await
+				.unwrap()
+				.good
+				.is_some()
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_files() {
+		let wrong_answer = r#"{
+			"choice": [{"question": "?", "answers": ["a", "b", "c", "d"], "good": 5, "theme": 1}],
+			"tip": [{"question": "?", "good": 10, "theme": 1}]
+		}"#;
+		assert!(JsonQuestions::from_json(wrong_answer).is_err());
+		let no_tips = r#"{
+			"choice": [{"question": "?", "answers": ["a", "b", "c", "d"], "good": 2, "theme": 1}],
+			"tip": []
+		}"#;
+		assert!(JsonQuestions::from_json(no_tips).is_err());
+	}
+}
@@ -0,0 +1,104 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::game_player_data::PlayerName;
+use crate::users::ServerCommand;
+
+/// Something that happened during a game, `at_ms` is counted from the start of the game
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum ReplayEvent {
+	/// A public message sent to every player
+	State { at_ms: u64, xml: String },
+	/// A command received from a player
+	Command {
+		at_ms: u64,
+		player: u8,
+		command: String,
+	},
+}
+
+/// Every broadcast and every command of a game in order, clones share the same log
+#[derive(Clone, Debug)]
+pub(crate) struct ReplayLog {
+	started: Instant,
+	events: Arc<Mutex<Vec<ReplayEvent>>>,
+}
+
+impl ReplayLog {
+	pub(crate) fn new() -> ReplayLog {
+		ReplayLog {
+			started: Instant::now(),
+			events: Arc::new(Mutex::new(Vec::new())),
+		}
+	}
+
+	fn push(&self, event: ReplayEvent) {
+		match self.events.lock() {
+			Ok(mut events) => events.push(event),
+			Err(e) => error!("Replay log is poisoned: {}", e),
+		}
+	}
+
+	fn elapsed_ms(&self) -> u64 {
+		self.started.elapsed().as_millis() as u64
+	}
+
+	pub(crate) fn record_state(&self, xml: &str) {
+		let at_ms = self.elapsed_ms();
+		self.push(ReplayEvent::State {
+			at_ms,
+			xml: xml.to_string(),
+		});
+	}
+
+	pub(crate) fn record_command(&self, player: PlayerName, command: &ServerCommand) {
+		let at_ms = self.elapsed_ms();
+		self.push(ReplayEvent::Command {
+			at_ms,
+			player: player as u8,
+			command: format!("{:?}", command),
+		});
+	}
+
+	pub(crate) fn events(&self) -> Vec<ReplayEvent> {
+		self.events
+			.lock()
+			.map(|events| events.clone())
+			.unwrap_or_default()
+	}
+
+	pub(crate) fn to_json(&self) -> serde_json::Result<String> {
+		serde_json::to_string(&self.events())
+	}
+
+	pub(crate) fn from_json(json: &str) -> serde_json::Result<ReplayLog> {
+		Ok(ReplayLog {
+			started: Instant::now(),
+			events: Arc::new(Mutex::new(serde_json::from_str(json)?)),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn replay_json_roundtrip() {
+		let log = ReplayLog::new();
+		log.record_state("<ROOT><STATE/></ROOT>");
+		log.record_command(PlayerName::Player2, &ServerCommand::SelectArea(7));
+
+		let json = log.to_json().unwrap();
+		let loaded = ReplayLog::from_json(&json).unwrap();
+		assert_eq!(loaded.events(), log.events());
+		assert!(matches!(
+			&loaded.events()[1],
+			ReplayEvent::Command { player: 2, command, .. } if command == "SelectArea(7)"
+		));
+	}
+}
@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use super::war_order::WarOrder;
+
+/// What happens with the territory of a player who left the game
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ForfeitPolicy {
+	/// The areas stay with the player and a bot defends them
+	#[default]
+	KeepWithBot,
+	/// The areas and the base are removed from the map
+	LeaveNeutral,
+}
+
+impl FromStr for ForfeitPolicy {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"keep_with_bot" => Ok(ForfeitPolicy::KeepWithBot),
+			"leave_neutral" => Ok(ForfeitPolicy::LeaveNeutral),
+			_ => bail!("Unknown forfeit policy: {}", s),
+		}
+	}
+}
+
+/// Server side rules of a game
+#[derive(Clone, Debug)]
+pub(crate) struct GameRules {
+	pub forfeit_policy: ForfeitPolicy,
+	/// Most rounds of the area phase, it ends earlier when there are too few free counties
+	pub area_rounds: u8,
+	/// Rounds of the war phase, at most 6
+	pub war_rounds: u8,
+}
+
+impl Default for GameRules {
+	fn default() -> Self {
+		GameRules {
+			forfeit_policy: ForfeitPolicy::default(),
+			area_rounds: WarOrder::NORMAL_ROUND_COUNT,
+			war_rounds: WarOrder::NORMAL_ROUND_COUNT,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn forfeit_policy_from_str() {
+		assert_eq!(
+			ForfeitPolicy::from_str("leave_neutral").unwrap(),
+			ForfeitPolicy::LeaveNeutral
+		);
+		assert_eq!(
+			ForfeitPolicy::from_str("keep_with_bot").unwrap(),
+			ForfeitPolicy::KeepWithBot
+		);
+		assert!(ForfeitPolicy::from_str("neutral").is_err());
+	}
+}
@@ -41,7 +41,7 @@ impl Selection {
 					serialized.push_str("00");
 				}
 				Some(county) => {
-					let base_part = *county as u8;
+					let base_part = county.id();
 					let bytes = base_part.to_be_bytes();
 					serialized.push_str(to_hex_with_length(bytes.as_slice(), 2).as_str());
 				}
@@ -63,7 +63,10 @@ impl FromStr for Selection {
 		let mut rest: HashMap<PlayerName, County> = HashMap::with_capacity(3);
 		for (i, county_str) in vals.iter().enumerate() {
 			let value = u8::from_str_radix(county_str, 16)?;
-
+			// the player hasn't selected anything
+			if value == 0 {
+				continue;
+			}
 			rest.insert(
 				// increase by 1 because we don't have Player0
 				PlayerName::from(i as u8 + 1),
@@ -86,15 +89,15 @@ impl Serialize for Selection {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use crate::triviador::county::County;
+	use crate::triviador::county::hungary;
 	use crate::triviador::game_player_data::PlayerName;
 
 	#[test]
 	fn test_serialize() {
 		let mut selection = Selection::new();
-		selection.add_selection(PlayerName::Player1, County::HajduBihar);
-		selection.add_selection(PlayerName::Player2, County::Veszprem);
-		selection.add_selection(PlayerName::Player3, County::Csongrad);
+		selection.add_selection(PlayerName::Player1, hungary::HAJDU_BIHAR);
+		selection.add_selection(PlayerName::Player2, hungary::VESZPREM);
+		selection.add_selection(PlayerName::Player3, hungary::CSONGRAD);
 		let serialized = selection.serialize_full().unwrap();
 		assert_eq!(serialized, "090E0B");
 	}
@@ -102,19 +105,27 @@ mod tests {
 	#[test]
 	fn test_deserialize() {
 		let mut selection = Selection::new();
-		selection.add_selection(PlayerName::Player1, County::HajduBihar);
-		selection.add_selection(PlayerName::Player2, County::Veszprem);
-		selection.add_selection(PlayerName::Player3, County::Csongrad);
+		selection.add_selection(PlayerName::Player1, hungary::HAJDU_BIHAR);
+		selection.add_selection(PlayerName::Player2, hungary::VESZPREM);
+		selection.add_selection(PlayerName::Player3, hungary::CSONGRAD);
 		let serialized = Selection::from_str("090E0B").unwrap();
 		assert_eq!(serialized, selection);
 	}
 
+	#[test]
+	fn missing_selections_round_trip() {
+		let mut selection = Selection::new();
+		selection.add_selection(PlayerName::Player2, hungary::VESZPREM);
+		assert_eq!(selection.serialize_full().unwrap(), "000E00");
+		assert_eq!(Selection::from_str("000E00").unwrap(), selection);
+	}
+
 	#[test]
 	fn test_bases() {
 		let mut selection = Selection::new();
-		selection.add_selection(PlayerName::Player1, County::HajduBihar);
-		selection.add_selection(PlayerName::Player2, County::Veszprem);
-		selection.add_selection(PlayerName::Player3, County::Csongrad);
+		selection.add_selection(PlayerName::Player1, hungary::HAJDU_BIHAR);
+		selection.add_selection(PlayerName::Player2, hungary::VESZPREM);
+		selection.add_selection(PlayerName::Player3, hungary::CSONGRAD);
 		let serialized = Selection::from_str("090E0B").unwrap();
 		assert_eq!(serialized, selection);
 	}
@@ -1,5 +1,12 @@
+use rand::Rng;
+use rand::prelude::IteratorRandom;
 use serde::{Serialize, Serializer};
 
+use super::areas::Areas;
+use super::county::County;
+use super::game_player_data::PlayerName;
+use super::map::GameMap;
+
 #[derive(Debug, Clone)]
 pub struct ShieldMission {
 	pub shieldmission: i32,
@@ -16,3 +23,188 @@ impl Serialize for ShieldMission {
 		serializer.serialize_str(&s)
 	}
 }
+
+/// What a shield mission asks for, the county of a conquer mission is picked from the map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MissionKind {
+	DefendCastle { rounds: u8 },
+	ConquerCounty,
+}
+
+/// A shield mission which can be assigned to a player with its rewards
+#[derive(Debug, PartialEq)]
+pub(crate) struct MissionDefinition {
+	/// Sent in `@SMSR`
+	pub id: i32,
+	pub kind: MissionKind,
+	pub reward_golds: u32,
+	pub reward_xp: u32,
+}
+
+/// Every shield mission the server hands out
+pub(crate) static MISSIONS: [MissionDefinition; 3] = [
+	MissionDefinition {
+		id: 1,
+		kind: MissionKind::DefendCastle { rounds: 3 },
+		reward_golds: 300,
+		reward_xp: 50,
+	},
+	MissionDefinition {
+		id: 2,
+		kind: MissionKind::DefendCastle { rounds: 6 },
+		reward_golds: 600,
+		reward_xp: 100,
+	},
+	MissionDefinition {
+		id: 3,
+		kind: MissionKind::ConquerCounty,
+		reward_golds: 500,
+		reward_xp: 80,
+	},
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MissionObjective {
+	/// Keep the castle through the given number of war rounds
+	DefendCastle { rounds: u8 },
+	/// Own the county when the war is over
+	ConquerCounty(County),
+}
+
+/// The shield mission of a player assigned at the start of the game
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PlayerMission {
+	mission: &'static MissionDefinition,
+	objective: MissionObjective,
+	progress: u8,
+}
+
+impl PlayerMission {
+	pub(crate) fn new(
+		mission: &'static MissionDefinition,
+		map: &GameMap,
+		rng: &mut impl Rng,
+	) -> PlayerMission {
+		let objective = match mission.kind {
+			MissionKind::DefendCastle { rounds } => MissionObjective::DefendCastle { rounds },
+			MissionKind::ConquerCounty => MissionObjective::ConquerCounty(
+				map.counties()
+					.iter()
+					.copied()
+					.choose(rng)
+					.expect("Maps have at least one area"),
+			),
+		};
+		PlayerMission {
+			mission,
+			objective,
+			progress: 0,
+		}
+	}
+
+	pub(crate) fn random(map: &GameMap, rng: &mut impl Rng) -> PlayerMission {
+		let mission = MISSIONS.iter().choose(rng).expect("There are missions");
+		PlayerMission::new(mission, map, rng)
+	}
+
+	/// Called at the end of every war round the player survived
+	pub(crate) fn war_round_survived(&mut self) {
+		if let MissionObjective::DefendCastle { rounds } = self.objective {
+			self.progress = (self.progress + 1).min(rounds);
+		}
+	}
+
+	/// Called after every battle, the county has to be owned when the war is over
+	pub(crate) fn track_ownership(&mut self, areas: &Areas, player: PlayerName) {
+		if let MissionObjective::ConquerCounty(county) = self.objective {
+			let owned = areas
+				.get_area(&county)
+				.is_some_and(|area| area.owner == player);
+			self.progress = owned as u8;
+		}
+	}
+
+	pub(crate) fn is_completed(&self) -> bool {
+		match self.objective {
+			MissionObjective::DefendCastle { rounds } => self.progress >= rounds,
+			MissionObjective::ConquerCounty(_) => self.progress == 1,
+		}
+	}
+
+	pub(crate) fn reward_golds(&self) -> u32 {
+		if self.is_completed() {
+			self.mission.reward_golds
+		} else {
+			0
+		}
+	}
+
+	pub(crate) fn reward_xp(&self) -> u32 {
+		if self.is_completed() {
+			self.mission.reward_xp
+		} else {
+			0
+		}
+	}
+
+	/// The mission as sent in `@SMSR`, the id of the mission and its progress in the format of
+	/// the `@SMSR` of the village setup (`0,0` without a mission). The mission list of the client
+	/// isn't known, so the ids are the ones of [`MISSIONS`]
+	pub(crate) fn shield_mission(&self) -> ShieldMission {
+		ShieldMission {
+			shieldmission: self.mission.id,
+			shieldmission_rt: i32::from(self.progress),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::triviador::county::hungary;
+
+	#[test]
+	fn defend_castle_progress() {
+		let mut rng = StdRng::seed_from_u64(1);
+		let mut mission = PlayerMission::new(&MISSIONS[0], &GameMap::hungary(), &mut rng);
+		mission.war_round_survived();
+		mission.war_round_survived();
+		assert!(!mission.is_completed());
+		assert_eq!(mission.reward_golds(), 0);
+		mission.war_round_survived();
+		mission.war_round_survived();
+		assert!(mission.is_completed());
+		assert_eq!(mission.reward_golds(), 300);
+		assert_eq!(mission.reward_xp(), 50);
+		assert_eq!(
+			quick_xml::se::to_string_with_root("SMSR", &mission.shield_mission()).unwrap(),
+			"<SMSR>1,3</SMSR>"
+		);
+	}
+
+	#[test]
+	fn conquer_county_progress() {
+		let map = GameMap::hungary();
+		let areas = Areas::parse(map.clone(), "13434343434342424242434141421112414243").unwrap();
+		let mut mission = PlayerMission {
+			mission: &MISSIONS[2],
+			objective: MissionObjective::ConquerCounty(hungary::PEST),
+			progress: 0,
+		};
+		mission.track_ownership(&areas, PlayerName::Player1);
+		assert!(!mission.is_completed());
+		mission.track_ownership(&areas, PlayerName::Player3);
+		assert!(mission.is_completed());
+		assert_eq!(mission.reward_golds(), MISSIONS[2].reward_golds);
+	}
+
+	#[test]
+	fn mission_ids_are_unique() {
+		for (i, mission) in MISSIONS.iter().enumerate() {
+			assert!(MISSIONS[i + 1..].iter().all(|other| other.id != mission.id));
+		}
+	}
+}
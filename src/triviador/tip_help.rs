@@ -0,0 +1,286 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_with::skip_serializing_none;
+
+use super::game_player_data::PlayerName;
+
+/// The closest tip given to the recently asked tip questions in the earlier games
+static PREVIOUS_TIPS: LazyLock<Mutex<PreviousTips>> =
+	LazyLock::new(|| Mutex::new(PreviousTips::new(PreviousTips::CAPACITY)));
+
+/// Closest tips by the text of the question, the oldest question is forgotten when it's full
+#[derive(Debug)]
+struct PreviousTips {
+	capacity: usize,
+	tips: HashMap<String, i32>,
+	order: VecDeque<String>,
+}
+
+impl PreviousTips {
+	const CAPACITY: usize = 1000;
+
+	fn new(capacity: usize) -> PreviousTips {
+		PreviousTips {
+			capacity,
+			tips: HashMap::new(),
+			order: VecDeque::new(),
+		}
+	}
+
+	fn get(&self, question: &str) -> Option<i32> {
+		self.tips.get(question).copied()
+	}
+
+	fn insert(&mut self, question: &str, tip: i32) {
+		if self.tips.insert(question.to_string(), tip).is_some() {
+			return;
+		}
+		self.order.push_back(question.to_string());
+		while self.order.len() > self.capacity {
+			let Some(oldest) = self.order.pop_front() else {
+				break;
+			};
+			self.tips.remove(&oldest);
+		}
+	}
+}
+
+/// Helps (jokers) a player can buy while a tip question is open
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub(crate) enum TipHelp {
+	/// Reveals a range which contains the correct value
+	#[serde(rename = "RANGE")]
+	Range,
+	/// Reveals the closest tip given to the same question in an earlier game
+	#[serde(rename = "CLOSEST")]
+	Closest,
+}
+
+impl TipHelp {
+	const ALL: [TipHelp; 2] = [TipHelp::Range, TipHelp::Closest];
+
+	pub(crate) fn cost(&self) -> u16 {
+		match self {
+			TipHelp::Range => 1500,
+			TipHelp::Closest => 2000,
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			TipHelp::Range => "RANGE",
+			TipHelp::Closest => "CLOSEST",
+		}
+	}
+
+	fn flag(&self) -> u8 {
+		match self {
+			TipHelp::Range => 1,
+			TipHelp::Closest => 2,
+		}
+	}
+
+	/// The `@HELP` attribute of a tip question, e.g. `{RANGE:1500,CLOSEST:2000}`
+	pub(crate) fn help_description() -> String {
+		let helps = Self::ALL
+			.iter()
+			.map(|help| format!("{}:{}", help.name(), help.cost()))
+			.collect::<Vec<_>>()
+			.join(",");
+		format!("{{{}}}", helps)
+	}
+
+	/// Creates a range which contains the correct value at a random position,
+	/// so the answer can't be calculated from the middle of the range
+	pub(crate) fn range_hint(good: i32, rng: &mut impl Rng) -> (i32, i32) {
+		// the range is 20% of the correct value, but at least 10 wide
+		let width = (good.abs() / 5).max(10);
+		let min = good - rng.gen_range(0..=width);
+		(min, min + width)
+	}
+
+	/// Returns the tip closest to the correct value, on equal distance the smaller one
+	pub(crate) fn closest_hint(good: i32, tips: impl Iterator<Item = i32>) -> Option<i32> {
+		tips.min_by_key(|tip| ((good - tip).abs(), *tip))
+	}
+
+	/// Remembers the closest of the tips given to the question, after the question is closed.
+	/// Exact tips are left out, the hint must not give the answer away
+	pub(crate) fn record_tips(question: &str, good: i32, tips: impl Iterator<Item = i32>) {
+		let mut previous_tips = PREVIOUS_TIPS.lock().unwrap();
+		let previous = previous_tips.get(question);
+		let tips = tips.filter(|tip| *tip != good).chain(previous);
+		if let Some(closest) = Self::closest_hint(good, tips) {
+			previous_tips.insert(question, closest);
+		}
+	}
+
+	/// The closest tip given to the question in an earlier game
+	pub(crate) fn previous_closest(question: &str) -> Option<i32> {
+		PREVIOUS_TIPS.lock().unwrap().get(question)
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HelpRequest {
+	#[serde(rename = "@HELP")]
+	pub help: TipHelp,
+}
+
+/// Sent only to the player who bought the help
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+#[serde(rename = "ROOT")]
+pub(crate) struct TipHelpResponse {
+	#[serde(rename = "TIPHELP")]
+	pub tip_help: TipHelpResult,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+pub(crate) struct TipHelpResult {
+	#[serde(rename = "@HELP")]
+	pub help: TipHelp,
+	#[serde(rename = "@MIN")]
+	pub min: Option<i32>,
+	#[serde(rename = "@MAX")]
+	pub max: Option<i32>,
+	#[serde(rename = "@VALUE")]
+	pub value: Option<i32>,
+}
+
+impl TipHelpResponse {
+	pub(crate) fn range(min: i32, max: i32) -> TipHelpResponse {
+		TipHelpResponse {
+			tip_help: TipHelpResult {
+				help: TipHelp::Range,
+				min: Some(min),
+				max: Some(max),
+				value: None,
+			},
+		}
+	}
+
+	pub(crate) fn closest(value: i32) -> TipHelpResponse {
+		TipHelpResponse {
+			tip_help: TipHelpResult {
+				help: TipHelp::Closest,
+				min: None,
+				max: None,
+				value: Some(value),
+			},
+		}
+	}
+}
+
+/// Every help used by the players during the game
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UsedHelps(HashMap<PlayerName, u8>);
+
+impl UsedHelps {
+	pub(crate) fn new() -> UsedHelps {
+		UsedHelps(HashMap::new())
+	}
+
+	pub(crate) fn add(&mut self, player: PlayerName, help: TipHelp) {
+		*self.0.entry(player).or_insert(0) |= help.flag();
+	}
+}
+
+impl Serialize for UsedHelps {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		// nothing used is sent as a single 0, otherwise every player gets their own flags
+		if self.0.values().all(|flags| *flags == 0) {
+			return serializer.serialize_str("0");
+		}
+		let s = PlayerName::all()
+			.map(|player| self.0.get(&player).copied().unwrap_or(0).to_string())
+			.collect::<Vec<_>>()
+			.join(",");
+		serializer.serialize_str(&s)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use serde_test::{Token, assert_ser_tokens};
+
+	use super::*;
+
+	#[test]
+	fn help_description() {
+		assert_eq!(TipHelp::help_description(), "{RANGE:1500,CLOSEST:2000}");
+	}
+
+	#[test]
+	fn range_contains_good() {
+		let mut rng = StdRng::seed_from_u64(7);
+		for good in [-50, 0, 1, 42, 1848, 100_000] {
+			let (min, max) = TipHelp::range_hint(good, &mut rng);
+			assert!(min <= good && good <= max);
+			assert_eq!(max - min, (good.abs() / 5).max(10));
+		}
+	}
+
+	#[test]
+	fn closest_tip() {
+		assert_eq!(
+			TipHelp::closest_hint(100, vec![80, 130, 95].into_iter()),
+			Some(95)
+		);
+		assert_eq!(
+			TipHelp::closest_hint(100, vec![90, 110].into_iter()),
+			Some(90)
+		);
+		assert_eq!(TipHelp::closest_hint(100, vec![].into_iter()), None);
+	}
+
+	#[test]
+	fn closest_previous_tip() {
+		let question = "Mikor volt a tatárjárás?";
+		assert_eq!(TipHelp::previous_closest(question), None);
+		TipHelp::record_tips(question, 1241, vec![1300, 1200].into_iter());
+		assert_eq!(TipHelp::previous_closest(question), Some(1200));
+		TipHelp::record_tips(question, 1241, vec![1500, 1000].into_iter());
+		assert_eq!(TipHelp::previous_closest(question), Some(1200));
+		TipHelp::record_tips(question, 1241, vec![1240].into_iter());
+		assert_eq!(TipHelp::previous_closest(question), Some(1240));
+		// the answer itself is never a hint
+		TipHelp::record_tips(question, 1241, vec![1241].into_iter());
+		assert_eq!(TipHelp::previous_closest(question), Some(1240));
+		let exact = "Mikor volt a mohácsi csata?";
+		TipHelp::record_tips(exact, 1526, vec![1526].into_iter());
+		assert_eq!(TipHelp::previous_closest(exact), None);
+	}
+
+	#[test]
+	fn previous_tips_are_bounded() {
+		let mut previous = PreviousTips::new(2);
+		previous.insert("first", 1);
+		previous.insert("second", 2);
+		previous.insert("first", 3);
+		previous.insert("third", 4);
+		assert_eq!(previous.get("first"), None);
+		assert_eq!(previous.get("second"), Some(2));
+		assert_eq!(previous.get("third"), Some(4));
+		assert_eq!(previous.tips.len(), 2);
+	}
+
+	#[test]
+	fn used_helps_serialize() {
+		let mut used = UsedHelps::new();
+		assert_ser_tokens(&used, &[Token::Str("0")]);
+		used.add(PlayerName::Player2, TipHelp::Range);
+		used.add(PlayerName::Player2, TipHelp::Closest);
+		used.add(PlayerName::Player3, TipHelp::Closest);
+		assert_ser_tokens(&used, &[Token::Str("0,3,2")]);
+	}
+}
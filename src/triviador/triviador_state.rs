@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
@@ -6,14 +8,14 @@ use super::fill_round::FillRound;
 use super::game_player_data::PlayerName;
 use super::player_points::PlayerPoints;
 use super::war_order::WarOrder;
-use crate::app::ServerCommandChannel;
-use crate::app::ListenPlayerChannel;
 use crate::triviador::available_area::AvailableAreas;
 use crate::triviador::bases::Bases;
+use crate::triviador::county::County;
 use crate::triviador::game_state::GameState;
 use crate::triviador::round_info::RoundInfo;
 use crate::triviador::selection::Selection;
-use crate::triviador::shield_mission::ShieldMission;
+use crate::triviador::shield_mission::{PlayerMission, ShieldMission};
+use crate::triviador::tip_help::UsedHelps;
 
 /// Represents the state of a Triviador game
 /// Expensive to clone
@@ -42,7 +44,7 @@ pub(crate) struct TriviadorState {
 	#[serde(rename = "@AA")]
 	pub available_areas: AvailableAreas,
 	#[serde(rename = "@UH")]
-	pub used_helps: String,
+	pub used_helps: UsedHelps,
 	#[serde(rename = "@FAO")]
 	pub fill_round_winners: FillRound,
 	// possibly unused
@@ -56,6 +58,203 @@ pub(crate) struct TriviadorState {
 	// todo remove
 	#[serde(skip)]
 	pub active_player: Option<PlayerName>,
+	/// Players who lost their castle or left the game
 	#[serde(skip)]
 	pub eliminated_players: Vec<PlayerName>,
+	/// Players who left the game, it counts as a loss
+	#[serde(skip)]
+	pub forfeited_players: Vec<PlayerName>,
+	/// Seats of players who left the game which a bot plays on under
+	/// `ForfeitPolicy::KeepWithBot`
+	#[serde(skip)]
+	pub bot_seats: Vec<PlayerName>,
+	/// Tied players in the order the tiebreaker ranked them
+	#[serde(skip)]
+	pub tiebreak_ranking: Vec<PlayerName>,
+	/// Shield missions of the players, only sent to their owner
+	#[serde(skip)]
+	pub missions: HashMap<PlayerName, PlayerMission>,
+}
+
+/// Points a rebuilt castle tower is worth to the owner of the castle
+pub(crate) const TOWER_POINTS: i16 = 100;
+/// Points a fortress is worth to the owner of the area, the attacker takes them if it falls
+pub(crate) const FORTRESS_POINTS: i16 = 100;
+/// The client hides the spreading rounds left once this few counties are free, the rest of them
+/// are filled by tip questions
+pub(crate) const FILL_REMAINING_FREE_COUNTIES: usize = 3;
+
+impl TriviadorState {
+	/// Seats still in the game, the seats of eliminated players are only played on by a bot
+	pub(crate) fn remaining_players(&self) -> Vec<PlayerName> {
+		PlayerName::all()
+			.filter(|player| self.is_in_play(player))
+			.collect()
+	}
+
+	pub(crate) fn is_in_play(&self, player: &PlayerName) -> bool {
+		!self.eliminated_players.contains(player) || self.bot_seats.contains(player)
+	}
+
+	/// The seat lost its castle, nobody plays it on
+	pub(crate) fn eliminate(&mut self, player: PlayerName) {
+		self.bot_seats.retain(|seat| *seat != player);
+		if !self.eliminated_players.contains(&player) {
+			self.eliminated_players.push(player);
+		}
+	}
+
+	/// Spreading goes on while every remaining player could still get a free county and the client
+	/// still shows the spreading rounds
+	pub(crate) fn spreading_continues(&self) -> bool {
+		let free = self.areas_info.free_counties().len();
+		free > FILL_REMAINING_FREE_COUNTIES && free >= self.remaining_players().len()
+	}
+
+	/// Rebuilds a destroyed tower of the castle of the player, false if none is destroyed
+	pub(crate) fn rebuild_tower(&mut self, player: PlayerName) -> bool {
+		let built = self
+			.base_info
+			.get_base_mut(&player)
+			.is_some_and(|base| base.build_tower());
+		if built {
+			self.players_points
+				.change_player_points(&player, TOWER_POINTS);
+		}
+		built
+	}
+
+	/// Fortifies a basic area for its owner, false if it's a castle or a fortress already
+	pub(crate) fn fortify(&mut self, county: County) -> bool {
+		let Some(area) = self.areas_info.get_area_mut(&county) else {
+			return false;
+		};
+		if area.is_fortress() || area.is_castle() {
+			return false;
+		}
+		area.fortify();
+		let owner = area.owner;
+		self.players_points
+			.change_player_points(&owner, FORTRESS_POINTS);
+		true
+	}
+
+	/// The fortress of the area absorbed a won attack, the area stays with its owner but the
+	/// points of the fortress go to the attacker
+	pub(crate) fn absorb_attack(&mut self, county: County, attacker: PlayerName) {
+		let Some(area) = self.areas_info.get_area_mut(&county) else {
+			return;
+		};
+		if !area.is_fortress() {
+			return;
+		}
+		area.destroy_fortress();
+		let owner = area.owner;
+		self.players_points
+			.change_player_points(&owner, -FORTRESS_POINTS);
+		self.players_points
+			.change_player_points(&attacker, FORTRESS_POINTS);
+	}
+
+	/// Sets `@SMSR` to the shield mission of the player the state is sent to
+	pub(crate) fn set_shield_mission(&mut self, player: &PlayerName) {
+		self.shield_mission = self.missions.get(player).map(PlayerMission::shield_mission);
+	}
+
+	/// Advances the shield missions after a battle resolved
+	pub(crate) fn track_missions(&mut self) {
+		for (player, mission) in self.missions.iter_mut() {
+			mission.track_ownership(&self.areas_info, *player);
+		}
+	}
+
+	/// Advances the shield missions of the players who survived a war round
+	pub(crate) fn war_round_over(&mut self) {
+		for (player, mission) in self.missions.iter_mut() {
+			if !self.eliminated_players.contains(player) {
+				mission.war_round_survived();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::app::tests::test_game;
+	use crate::triviador::bases::Base;
+	use crate::triviador::county::hungary;
+	use crate::triviador::map::GameMap;
+
+	async fn state() -> TriviadorState {
+		let game = test_game(None);
+		Bases::add_base(game.arc_clone(), PlayerName::Player3, Base::new(1))
+			.await
+			.unwrap();
+		let mut state = game.read().await.state.clone();
+		state.areas_info =
+			Areas::parse(GameMap::hungary(), "13434343434342424242434141421112414243").unwrap();
+		state
+	}
+
+	#[tokio::test]
+	async fn spreading_stops_at_the_client_threshold() {
+		let mut state = state().await;
+		assert!(!state.spreading_continues());
+		for county in [hungary::BACS_KISKUN, hungary::BARANYA, hungary::BEKES] {
+			state.areas_info.get_area_mut(&county).unwrap().owner = PlayerName::Nobody;
+		}
+		assert!(!state.spreading_continues());
+		state.areas_info.get_area_mut(&hungary::VAS).unwrap().owner = PlayerName::Nobody;
+		assert!(state.spreading_continues());
+	}
+
+	#[tokio::test]
+	async fn rebuilt_towers_earn_points() {
+		let mut state = state().await;
+		assert!(!state.rebuild_tower(PlayerName::Player3));
+		state
+			.base_info
+			.get_base_mut(&PlayerName::Player3)
+			.unwrap()
+			.destroy_tower();
+		assert!(state.rebuild_tower(PlayerName::Player3));
+		assert_eq!(
+			state.players_points.get_player_points(&PlayerName::Player3),
+			TOWER_POINTS
+		);
+		assert!(!state.rebuild_tower(PlayerName::Player1));
+	}
+
+	#[tokio::test]
+	async fn fortresses_earn_points_until_they_fall() {
+		let mut state = state().await;
+		// castles aren't fortified
+		assert!(!state.fortify(hungary::PEST));
+		assert!(state.fortify(hungary::BACS_KISKUN));
+		assert!(!state.fortify(hungary::BACS_KISKUN));
+		assert_eq!(
+			state.players_points.get_player_points(&PlayerName::Player3),
+			FORTRESS_POINTS
+		);
+
+		state.absorb_attack(hungary::BACS_KISKUN, PlayerName::Player1);
+		let area = state.areas_info.get_area(&hungary::BACS_KISKUN).unwrap();
+		assert!(!area.is_fortress());
+		assert_eq!(area.owner, PlayerName::Player3);
+		assert_eq!(
+			state.players_points.get_player_points(&PlayerName::Player3),
+			0
+		);
+		assert_eq!(
+			state.players_points.get_player_points(&PlayerName::Player1),
+			FORTRESS_POINTS
+		);
+		// only a fortress absorbs attacks
+		state.absorb_attack(hungary::BACS_KISKUN, PlayerName::Player1);
+		assert_eq!(
+			state.players_points.get_player_points(&PlayerName::Player1),
+			FORTRESS_POINTS
+		);
+	}
 }
@@ -1,81 +1,108 @@
-use rand::SeedableRng;
-use rand::rngs::StdRng;
+use rand::Rng;
 use rand::seq::SliceRandom;
 use serde::{Serialize, Serializer};
 use tracing::error;
 
 use super::game_player_data::PlayerName;
+use super::player_points::PlayerPoints;
 
-// todo make enum struct
-#[derive(Debug, Clone)]
+/// The order of players in every round of the area and war phases, every round has three slots
+/// and the missing players are `Nobody`, the client reads it in groups of three
+#[derive(Debug, Clone, PartialEq)]
 pub struct WarOrder {
-	pub order: Vec<PlayerName>,
+	rounds: Vec<[PlayerName; 3]>,
 }
 
-// todo account for players getting a base
 impl WarOrder {
 	pub(crate) const NORMAL_ROUND_COUNT: u8 = 6;
-	pub(crate) fn new_random_with_size(mut round_count: u8) -> WarOrder {
-		let mut rng = StdRng::from_entropy();
-		if round_count > 6 {
+	const ROUND_SLOTS: usize = 3;
+
+	/// Every round ordered by the current points of the players, equal points are shuffled
+	pub(crate) fn ranked(
+		points: &PlayerPoints,
+		players: &[PlayerName],
+		round_count: u8,
+		rng: &mut impl Rng,
+	) -> WarOrder {
+		let mut round_count = round_count;
+		if round_count > Self::NORMAL_ROUND_COUNT {
 			error!("Round count can't be more than 6, consider splitting it to multiple states");
-			round_count = 6;
+			round_count = Self::NORMAL_ROUND_COUNT;
 		}
-		let mut order = Vec::with_capacity(round_count as usize * 3);
-		for _ in 1..=round_count {
-			let mut vec: Vec<PlayerName> = vec![
-				PlayerName::Player1,
-				PlayerName::Player2,
-				PlayerName::Player3,
-			];
-			vec.shuffle(&mut rng);
-			order.append(&mut vec);
+		let mut wo = WarOrder {
+			rounds: vec![[PlayerName::Nobody; Self::ROUND_SLOTS]; round_count as usize],
+		};
+		wo.rerank_from(0, points, players, rng);
+		wo
+	}
+
+	/// Recalculates the order of the round and every round after it, the points leader goes first
+	/// and the last player goes last
+	pub(crate) fn rerank_from(
+		&mut self,
+		round: usize,
+		points: &PlayerPoints,
+		players: &[PlayerName],
+		rng: &mut impl Rng,
+	) {
+		for next in self.rounds.iter_mut().skip(round) {
+			let mut ranking = players.to_vec();
+			ranking.shuffle(rng);
+			ranking.sort_by_key(|player| std::cmp::Reverse(points.get_player_points(player)));
+			*next = Self::padded(&ranking);
 		}
-		WarOrder { order }
 	}
 
-	pub(crate) fn standard_round() -> WarOrder {
-		// todo make the last round calculated
-		// the first should be the point leader, second is the second player, and the last is the last player
-		let mut order = Vec::with_capacity(18);
-		for round in 0..6 {
-			// this shifts around players starting from the first player in each round
-			let start = round % 3;
-			order.push(PlayerName::from((start + 1) as u8));
-			order.push(PlayerName::from(((start + 1) % 3 + 1) as u8));
-			order.push(PlayerName::from(((start + 2) % 3 + 1) as u8));
+	/// Drops the rounds after the given number of rounds
+	pub(crate) fn truncate(&mut self, round_count: usize) {
+		self.rounds.truncate(round_count);
+	}
+
+	/// The players of a round in order, empty if the round doesn't exist
+	pub(crate) fn round(&self, round: usize) -> Vec<PlayerName> {
+		self.rounds
+			.get(round)
+			.map(|slots| {
+				slots
+					.iter()
+					.copied()
+					.filter(|player| *player != PlayerName::Nobody)
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Fills the empty slots of a round with `Nobody`
+	fn padded(players: &[PlayerName]) -> [PlayerName; 3] {
+		let mut slots = [PlayerName::Nobody; Self::ROUND_SLOTS];
+		for (slot, player) in slots.iter_mut().zip(players) {
+			*slot = *player;
 		}
-		WarOrder::from(order)
+		slots
 	}
 
 	fn serialize(&self) -> String {
 		let mut serialized = "".to_string();
-		for rel_id in self.order.iter() {
+		for rel_id in self.rounds.iter().flatten() {
 			serialized.push_str((*rel_id as u8).to_string().as_str());
 		}
 		serialized
 	}
-
-	pub(crate) fn get_next_players(
-		&self,
-		start: usize,
-		amount: usize,
-	) -> Result<Vec<PlayerName>, anyhow::Error> {
-		let players = self.order[start..(start + amount)].to_vec();
-		Ok(players)
-	}
 }
 
 impl From<Vec<PlayerName>> for WarOrder {
-	fn from(counties: Vec<PlayerName>) -> Self {
-		WarOrder { order: counties }
+	/// Splits the order into rounds of three
+	fn from(order: Vec<PlayerName>) -> Self {
+		WarOrder {
+			rounds: order.chunks(Self::ROUND_SLOTS).map(Self::padded).collect(),
+		}
 	}
 }
 
 impl From<Vec<u8>> for WarOrder {
 	fn from(counties: Vec<u8>) -> Self {
 		let order: Vec<PlayerName> = counties.iter().map(|x| PlayerName::from(*x)).collect();
-		WarOrder { order }
+		WarOrder::from(order)
 	}
 }
 
@@ -90,14 +117,17 @@ impl Serialize for WarOrder {
 
 #[cfg(test)]
 mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
 	use super::*;
 
 	#[test]
-	fn test_get_next_players() {
+	fn test_round() {
 		let wo = WarOrder::from(vec![1, 2, 3, 3, 2, 1]);
 
 		assert_eq!(
-			wo.get_next_players(0, 3).unwrap(),
+			wo.round(0),
 			vec![
 				PlayerName::Player1,
 				PlayerName::Player2,
@@ -105,12 +135,68 @@ mod tests {
 			]
 		);
 		assert_eq!(
-			wo.get_next_players(3, 3).unwrap(),
+			wo.round(1),
 			vec![
 				PlayerName::Player3,
 				PlayerName::Player2,
 				PlayerName::Player1
 			]
 		);
+		assert!(wo.round(2).is_empty());
+	}
+
+	#[test]
+	fn ranked_by_points() {
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player1, 1200);
+		points.set_player_points(&PlayerName::Player2, 1000);
+		points.set_player_points(&PlayerName::Player3, 1800);
+		let mut rng = StdRng::seed_from_u64(7);
+		let mut wo = WarOrder::ranked(
+			&points,
+			&[PlayerName::Player1, PlayerName::Player3],
+			2,
+			&mut rng,
+		);
+		assert!(wo.round(2).is_empty());
+		assert_eq!(wo.round(1), vec![PlayerName::Player3, PlayerName::Player1]);
+
+		points.set_player_points(&PlayerName::Player1, 2000);
+		wo.rerank_from(1, &points, &PlayerName::all().collect::<Vec<_>>(), &mut rng);
+		assert_eq!(wo.round(0), vec![PlayerName::Player3, PlayerName::Player1]);
+		assert_eq!(
+			wo.round(1),
+			vec![
+				PlayerName::Player1,
+				PlayerName::Player3,
+				PlayerName::Player2
+			]
+		);
+		let serialized = quick_xml::se::to_string_with_root("WO", &wo).unwrap();
+		assert_eq!(serialized, "<WO>310132</WO>");
+	}
+
+	#[test]
+	fn reranked_order_round_trip() {
+		let mut points = PlayerPoints::new();
+		points.set_player_points(&PlayerName::Player1, 1400);
+		points.set_player_points(&PlayerName::Player2, 600);
+		points.set_player_points(&PlayerName::Player3, 1000);
+		let mut rng = StdRng::seed_from_u64(3);
+		let mut wo = WarOrder::ranked(&points, &PlayerName::all().collect::<Vec<_>>(), 4, &mut rng);
+		wo.rerank_from(
+			2,
+			&points,
+			&[PlayerName::Player3, PlayerName::Player2],
+			&mut rng,
+		);
+
+		let serialized = wo.serialize();
+		assert_eq!(serialized, "132132320320");
+		let digits = serialized
+			.bytes()
+			.map(|digit| digit - b'0')
+			.collect::<Vec<_>>();
+		assert_eq!(WarOrder::from(digits), wo);
 	}
 }
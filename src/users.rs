@@ -1,9 +1,14 @@
+use crate::triviador::tip_help::TipHelp;
+
 #[derive(Clone, Debug)]
 pub enum ServerCommand {
 	SelectArea(u8),
 	QuestionAnswer(u8),
 	TipAnswer(i32),
+	UseHelp(TipHelp),
 	Ready,
+	/// Wakes up a receiver which waits on channels replaced by a rejoin
+	ChannelsReplaced,
 }
 
 impl ServerCommand {
@@ -15,7 +20,9 @@ impl ServerCommand {
 			(ServerCommand::SelectArea(_), ServerCommand::SelectArea(_)) => true,
 			(ServerCommand::QuestionAnswer(_), ServerCommand::QuestionAnswer(_)) => true,
 			(ServerCommand::TipAnswer(_), ServerCommand::TipAnswer(_)) => true,
+			(ServerCommand::UseHelp(_), ServerCommand::UseHelp(_)) => true,
 			(ServerCommand::Ready, ServerCommand::Ready) => true,
+			(ServerCommand::ChannelsReplaced, ServerCommand::ChannelsReplaced) => true,
 			_ => false,
 		}
 	}
@@ -4,12 +4,15 @@ use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_aux::prelude::deserialize_number_from_string;
 use serde_with::skip_serializing_none;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use crate::app::{
-	GamePlayerChannels, GroupedCommChannels, ListenPlayerChannel, ServerCommandChannel,
+	FriendlyRooms, GamePlayerChannels, GroupedCommChannels, ListenPlayerChannel,
+	ServerCommandChannel,
 };
+use crate::bot::strategy::BotDifficulty;
 use crate::emulator::Emulator;
+use crate::triviador::game::SharedTrivGame;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ExitCurrentRoom {}
@@ -21,7 +24,7 @@ pub(crate) enum OpponentType {
 	// 0
 	Anyone,
 	// -1
-	Robot,
+	Robot(BotDifficulty),
 	// -2
 	Code,
 }
@@ -31,10 +34,18 @@ impl OpponentType {
 		match self {
 			OpponentType::Player(id) => *id,
 			OpponentType::Anyone => 0,
-			OpponentType::Robot => -1,
+			OpponentType::Robot(_) => -1,
 			OpponentType::Code => -2,
 		}
 	}
+
+	/// Sets the difficulty if the opponent is a robot
+	pub(crate) fn with_bot_difficulty(self, difficulty: BotDifficulty) -> OpponentType {
+		match self {
+			OpponentType::Robot(_) => OpponentType::Robot(difficulty),
+			opponent => opponent,
+		}
+	}
 }
 
 impl FromStr for OpponentType {
@@ -43,7 +54,7 @@ impl FromStr for OpponentType {
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		match s {
 			"0" => Ok(OpponentType::Anyone),
-			"-1" => Ok(OpponentType::Robot),
+			"-1" => Ok(OpponentType::Robot(BotDifficulty::default())),
 			"-2" => Ok(OpponentType::Code),
 			_ => Ok(OpponentType::Player(i32::from_str(s)?)),
 		}
@@ -66,9 +77,29 @@ pub struct AddFriendlyRoom {
 	pub question_categories: String,
 	#[serde(rename = "@CHATMSG")]
 	pub chatmsg: String,
+	/// Name of the map, the default map if missing
+	#[serde(rename = "@MAP")]
+	pub map: Option<String>,
+	/// Difficulty of the robots from 1 to 3, normal if missing
+	#[serde(rename = "@BOTLEVEL")]
+	pub bot_level: Option<u8>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl AddFriendlyRoom {
+	pub(crate) fn bot_difficulty(&self) -> BotDifficulty {
+		match self.bot_level.map(BotDifficulty::try_from) {
+			Some(Ok(difficulty)) => difficulty,
+			Some(Err(e)) => {
+				warn!("{}, using the default difficulty", e);
+				BotDifficulty::default()
+			}
+			None => BotDifficulty::default(),
+		}
+	}
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct FriendlyRoom {
 	#[serde(rename = "@ID")]
 	pub room_id: u32,
@@ -80,6 +111,26 @@ pub struct FriendlyRoom {
 	pub player_2: String,
 	#[serde(rename = "@P3")]
 	pub player_3: String,
+	#[serde(rename = "@SPECTATORS")]
+	pub spectators: Option<u16>,
+}
+
+impl FriendlyRoom {
+	/// The entry of the room in the friendly room list, spectators are only sent if there are any
+	pub(crate) fn new(code: u16, room: &ActiveSepRoom) -> FriendlyRoom {
+		let seat = |opponent: &Option<OpponentType>, ready: bool| match opponent {
+			Some(opponent) => format!("{},{}", opponent.get_id(), ready as u8),
+			None => "0,0".to_string(),
+		};
+		FriendlyRoom {
+			room_id: u32::from(code),
+			player_1: format!("{},{}", room.player1.get_id(), room.player1_ready as u8),
+			name1: Some(room.player1_name.clone()),
+			player_2: seat(&room.player2, room.player2_ready),
+			player_3: seat(&room.player3, room.player3_ready),
+			spectators: (room.spectators > 0).then_some(room.spectators),
+		}
+	}
 }
 
 impl Emulator for FriendlyRoom {
@@ -90,6 +141,7 @@ impl Emulator for FriendlyRoom {
 			name1: Some("Hello".to_string()),
 			player_2: "0,0".to_string(),
 			player_3: "-1,0".to_string(),
+			spectators: None,
 		}
 	}
 }
@@ -101,6 +153,19 @@ pub struct FriendlyListRooms {
 	pub rooms: Vec<FriendlyRoom>,
 }
 
+impl FriendlyListRooms {
+	/// Every open friendly room ordered by code
+	pub(crate) async fn new(rooms: &FriendlyRooms) -> FriendlyListRooms {
+		let mut entries = Vec::new();
+		rooms
+			.0
+			.scan_async(|code, room| entries.push(FriendlyRoom::new(*code, room)))
+			.await;
+		entries.sort_by_key(|room| room.room_id);
+		FriendlyListRooms { rooms: entries }
+	}
+}
+
 impl Emulator for FriendlyListRooms {
 	fn emulate() -> Self {
 		FriendlyListRooms {
@@ -148,6 +213,13 @@ pub struct ActiveSepRoom {
 	pub can_start: bool,
 	#[serde(skip)]
 	listen_channel: GroupedCommChannels,
+	/// The running game of the room
+	#[serde(skip)]
+	pub game: Option<SharedTrivGame>,
+	#[serde(skip)]
+	pub spectators: u16,
+	#[serde(rename = "@MAP")]
+	pub map: Option<String>,
 }
 
 impl ActiveSepRoom {
@@ -165,6 +237,9 @@ impl ActiveSepRoom {
 			player3_name: None,
 			can_start: false,
 			listen_channel: GroupedCommChannels::new(),
+			game: None,
+			spectators: 0,
+			map: None,
 		}
 	}
 
@@ -173,7 +248,7 @@ impl ActiveSepRoom {
 		opponent_type: OpponentType,
 		name: Option<String>,
 	) -> anyhow::Result<()> {
-		let is_ready = matches!(opponent_type, OpponentType::Robot);
+		let is_ready = matches!(opponent_type, OpponentType::Robot(_));
 		let is_player = matches!(opponent_type, OpponentType::Player(_));
 		if self.can_add_opponent_to_slot(&self.player2, is_player) {
 			self.player2 = Some(opponent_type);
@@ -212,11 +287,11 @@ impl ActiveSepRoom {
 	}
 
 	pub(crate) fn check_playable(&mut self) {
-		if (matches!(self.player1, OpponentType::Robot)
+		if (matches!(self.player1, OpponentType::Robot(_))
 			|| matches!(self.player1, OpponentType::Player(_)))
-			&& (matches!(self.player2, Some(OpponentType::Robot))
+			&& (matches!(self.player2, Some(OpponentType::Robot(_)))
 				|| matches!(self.player2, Some(OpponentType::Player(_))))
-			&& (matches!(self.player3, Some(OpponentType::Robot))
+			&& (matches!(self.player3, Some(OpponentType::Robot(_)))
 				|| matches!(self.player3, Some(OpponentType::Player(_))))
 		{
 			self.allow_game();
@@ -310,7 +385,7 @@ impl Serialize for ActiveSepRoom {
 	where
 		S: Serializer,
 	{
-		let mut state = serializer.serialize_struct("ACTIVESEPROOM", 9)?;
+		let mut state = serializer.serialize_struct("ACTIVESEPROOM", 10)?;
 
 		if let Some(code) = &self.code {
 			state.serialize_field("@CODE", code)?;
@@ -344,6 +419,12 @@ impl Serialize for ActiveSepRoom {
 		if self.can_start {
 			state.serialize_field("@STARTDELAY", &())?;
 		}
+		if self.spectators > 0 {
+			state.serialize_field("@SPECTATORS", &self.spectators)?;
+		}
+		if let Some(map) = &self.map {
+			state.serialize_field("@MAP", map)?;
+		}
 		state.end()
 	}
 }
@@ -355,18 +436,77 @@ fn friendly_test() {
 		player1: OpponentType::Player(1),
 		player1_ready: false,
 		player1_name: "xrtxn".to_string(),
-		player2: Some(OpponentType::Robot),
+		player2: Some(OpponentType::Robot(BotDifficulty::Normal)),
 		player2_ready: false,
 		player2_name: None,
-		player3: Some(OpponentType::Robot),
+		player3: Some(OpponentType::Robot(BotDifficulty::Normal)),
 		player3_ready: false,
 		player3_name: None,
 		can_start: false,
 		listen_channel: GroupedCommChannels::new(),
+		game: None,
+		spectators: 0,
+		map: None,
 	};
 
 	let serialized = quick_xml::se::to_string(&room).unwrap();
 
 	let expected = r#"<ACTIVESEPROOM P1="1,0" PN1="xrtxn" P2="-1,0" P3="-1,0"/>"#;
 	assert_eq!(serialized, expected);
+
+	let room = ActiveSepRoom {
+		spectators: 2,
+		..room
+	};
+	let serialized = quick_xml::se::to_string(&room).unwrap();
+	let expected = r#"<ACTIVESEPROOM P1="1,0" PN1="xrtxn" P2="-1,0" P3="-1,0" SPECTATORS="2"/>"#;
+	assert_eq!(serialized, expected);
+
+	let room = ActiveSepRoom {
+		spectators: 0,
+		map: Some("MAP_WD".to_string()),
+		..room
+	};
+	let serialized = quick_xml::se::to_string(&room).unwrap();
+	let expected = r#"<ACTIVESEPROOM P1="1,0" PN1="xrtxn" P2="-1,0" P3="-1,0" MAP="MAP_WD"/>"#;
+	assert_eq!(serialized, expected);
+}
+
+#[tokio::test]
+async fn room_list_shows_spectators() {
+	let rooms = FriendlyRooms::new();
+	let mut room = ActiveSepRoom::new(OpponentType::Player(1), "xrtxn");
+	room.add_opponent(OpponentType::Robot(BotDifficulty::Normal), None)
+		.unwrap();
+	room.player1_ready = true;
+	room.spectators = 2;
+	rooms.insert_async(4321, room).await.unwrap();
+	rooms
+		.insert_async(1234, ActiveSepRoom::new(OpponentType::Player(6), "Lajos"))
+		.await
+		.unwrap();
+
+	let serialized = quick_xml::se::to_string(&FriendlyListRooms::new(&rooms).await).unwrap();
+	let expected = concat!(
+		r#"<ROOT><ROOM ID="1234" PN1="6,0" NAME1="Lajos" P2="0,0" P3="0,0"/>"#,
+		r#"<ROOM ID="4321" PN1="1,1" NAME1="xrtxn" P2="-1,1" P3="0,0" SPECTATORS="2"/></ROOT>"#
+	);
+	assert_eq!(serialized, expected);
+}
+
+#[test]
+fn add_room_bot_level() {
+	let xml =
+		r#"<ADDFRIENDLYROOM OPP1="-1" OPP2="-1" RULES="0" QCATS="" CHATMSG="" BOTLEVEL="3"/>"#;
+	let request: AddFriendlyRoom = quick_xml::de::from_str(xml).unwrap();
+	assert_eq!(
+		request.opp1.with_bot_difficulty(request.bot_difficulty()),
+		OpponentType::Robot(BotDifficulty::Hard)
+	);
+
+	let request = AddFriendlyRoom {
+		bot_level: Some(7),
+		..request
+	};
+	assert_eq!(request.bot_difficulty(), BotDifficulty::Normal);
 }